@@ -1,6 +1,9 @@
 use cosmic::cosmic_config::cosmic_config_derive::CosmicConfigEntry;
 use cosmic::cosmic_config::CosmicConfigEntry;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::focus::MatchMode;
 
 pub const APP_LIST_ID: &str = "com.system76.CosmicAppList";
 
@@ -30,3 +33,26 @@ impl Default for AppListConfig {
         }
     }
 }
+
+/// Settings owned by this applet, stored under its own app id.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq, CosmicConfigEntry)]
+#[version = 1]
+#[serde(deny_unknown_fields)]
+pub struct FocusAppletConfig {
+    /// Favorites that get minimized when activated while already focused.
+    pub minimize_on_repeat: Vec<String>,
+    /// Per-favorite window matching, keyed by favorite app id.
+    pub match_rules: BTreeMap<String, MatchRule>,
+    /// Key binding (e.g. `Super+Grave`) that switches back to the previously focused app.
+    pub previous_shortcut: Option<String>,
+}
+
+/// Overrides how a favorite's running windows are recognized.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct MatchRule {
+    pub mode: MatchMode,
+    /// Pattern used instead of the favorite's app id.
+    #[serde(default)]
+    pub pattern: Option<String>,
+}
@@ -1,23 +1,27 @@
 use cosmic::{
     app,
+    applet::token::subscription::{activation_token_subscription, TokenRequest, TokenUpdate},
+    cctk::sctk::reexports::calloop,
     cosmic_config::{Config, CosmicConfigEntry},
     desktop::fde::{self, get_languages_from_env, DesktopEntry},
-    iced::futures::SinkExt,
-    iced::{self, Alignment, Subscription},
-    iced_widget::Row,
+    iced::futures::{SinkExt, StreamExt},
+    iced::platform_specific::shell::commands::popup::{destroy_popup, get_popup},
+    iced::{self, window, Alignment, Subscription},
+    iced_widget::{Column, Row},
     surface,
-    widget::container,
+    widget::{container, mouse_area, text},
     Action, Element, Task,
 };
 mod config;
-use config::{AppListConfig, APP_LIST_ID};
+use clap::ValueEnum;
+use config::{AppListConfig, FocusAppletConfig, TopLevelFilter, APP_LIST_ID};
 use cosmic_settings_config::shortcuts::{
     Action as ShortcutAction, Binding, Config as ShortcutConfig,
 };
 use iced::stream;
 use rustc_hash::FxHashMap;
 use std::any::TypeId;
-use std::{borrow::Cow, collections::BTreeSet, str::FromStr, sync::mpsc, thread};
+use std::{borrow::Cow, collections::BTreeSet, str::FromStr, thread};
 
 use crate::focus;
 
@@ -36,20 +40,34 @@ struct AppButtonModel {
 pub struct FocusApplet {
     core: cosmic::app::Core,
     config: AppListConfig,
-    running: Vec<String>,
+    focus_config: FocusAppletConfig,
+    running: focus::RunningApps,
     items: Vec<AppButtonModel>,
     locales: Vec<String>,
     desktop_entries: Vec<DesktopEntry>,
     desktop_cache: FxHashMap<String, DesktopEntry>,
     shortcut_targets: Vec<String>,
+    /// The `previous` key binding last written to the shortcuts config.
+    previous_binding: Option<String>,
+    token_tx: Option<calloop::channel::Sender<TokenRequest>>,
+    /// The open right-click menu and the app it was opened for.
+    menu: Option<(window::Id, String)>,
+    /// Apps middle-clicked while their activation token is being requested.
+    summoning: BTreeSet<String>,
 }
 
 #[derive(Debug, Clone)]
 pub enum Message {
     Activate(String),
-    AppsUpdated(Vec<String>),
+    Summon(String),
+    OpenMenu(String),
+    MenuClosed(window::Id),
+    Quit(String),
+    Toplevel(focus::ToplevelEvent),
     ConfigUpdated(AppListConfig),
+    FocusConfigUpdated(FocusAppletConfig),
     Surface(surface::Action),
+    Token(TokenUpdate),
 }
 
 impl FocusApplet {
@@ -60,6 +78,115 @@ impl FocusApplet {
             .unwrap_or_default()
     }
 
+    fn load_focus_config() -> FocusAppletConfig {
+        Config::new(APP_ID, FocusAppletConfig::VERSION)
+            .ok()
+            // Keys missing from older configs fall back to their defaults.
+            .map(|cfg| FocusAppletConfig::get_entry(&cfg).unwrap_or_else(|(_, config)| config))
+            .unwrap_or_default()
+    }
+
+    fn focus_options(&self, app_id: &str) -> focus::FocusOptions {
+        let rule = self
+            .focus_config
+            .match_rules
+            .iter()
+            .find(|(id, _)| id.eq_ignore_ascii_case(app_id))
+            .map(|(_, rule)| rule.clone())
+            .unwrap_or_default();
+        focus::FocusOptions {
+            minimize_on_repeat: self
+                .focus_config
+                .minimize_on_repeat
+                .iter()
+                .any(|id| id.eq_ignore_ascii_case(app_id)),
+            match_mode: rule.mode,
+            pattern: rule.pattern,
+            policy: match self.config.filter_top_levels {
+                Some(TopLevelFilter::ConfiguredOutput) => focus::FocusPolicy::CurrentOutput,
+                Some(TopLevelFilter::ActiveWorkspace) | None => {
+                    focus::FocusPolicy::CurrentWorkspace
+                }
+            },
+            ..Default::default()
+        }
+    }
+
+    fn shortcut_command(&self, app_id: &str) -> String {
+        let options = self.focus_options(app_id);
+        let mut command = String::from("cosmic-app-focus");
+        if options.minimize_on_repeat {
+            command.push_str(" --minimize-on-repeat");
+        }
+        if options.match_mode != focus::MatchMode::default() {
+            if let Some(mode) = options.match_mode.to_possible_value() {
+                command.push_str(&format!(" --match {}", mode.get_name()));
+            }
+        }
+        if options.policy != focus::FocusPolicy::default() {
+            if let Some(policy) = options.policy.to_possible_value() {
+                command.push_str(&format!(" --policy {}", policy.get_name()));
+            }
+        }
+        if let Some(pattern) = options.pattern.as_deref() {
+            command.push_str(&format!(" --pattern {}", shell_quote(pattern)));
+        }
+        command.push_str(&format!(" {}", shell_quote(app_id)));
+        command
+    }
+
+    fn focus_or_launch(&mut self, app_id: &str, activation_token: Option<String>, summon: bool) {
+        let mut options = self.focus_options(app_id);
+        if options.policy == focus::FocusPolicy::CurrentOutput || summon {
+            // Clicks come from this panel, so its output is the one that counts.
+            options.output = Some(self.core.applet.output_name.clone());
+        }
+        options.activation_token = activation_token;
+        options.summon = summon;
+        options.desktop_entry = self.launch_entry(app_id);
+        let app_id = app_id.to_string();
+        spawn_blocking(move || {
+            if let Err(err) = focus::focus_or_launch(&app_id, &options) {
+                log::error!("Failed to focus {app_id}: {err}");
+            }
+        });
+    }
+
+    /// The cached desktop entry to launch `app_id` from, if it has a real one.
+    fn launch_entry(&mut self, app_id: &str) -> Option<DesktopEntry> {
+        // The stand-in made up for apps without a desktop file has no path.
+        Some(self.desktop_entry(app_id)).filter(|entry| !entry.path.as_os_str().is_empty())
+    }
+
+    /// Asks for an activation token first so a launched app may take focus; the request
+    /// carries the app id back in `exec`.
+    fn request_token(&mut self, app_id: String, summon: bool) {
+        let request = TokenRequest {
+            app_id: APP_ID.to_string(),
+            exec: app_id.clone(),
+        };
+        let requested = self
+            .token_tx
+            .as_ref()
+            .is_some_and(|tx| tx.send(request).is_ok());
+        if !requested {
+            self.focus_or_launch(&app_id, None, summon);
+        } else if summon {
+            self.summoning.insert(app_id);
+        } else {
+            self.summoning.remove(&app_id);
+        }
+    }
+
+    fn quit(&self, app_id: String) {
+        let options = self.focus_options(&app_id);
+        spawn_blocking(move || {
+            if let Err(err) = focus::close_app(&app_id, &options) {
+                log::error!("Failed to quit {app_id}: {err}");
+            }
+        });
+    }
+
     fn update_desktop_entries(&mut self) {
         self.desktop_entries = fde::Iter::new(fde::default_paths())
             .filter_map(|path| DesktopEntry::from_path(path, Some(&self.locales)).ok())
@@ -115,8 +242,10 @@ impl FocusApplet {
             }
         }
 
-        let mut extras: Vec<_> = self
+        // `running` is ordered most recently focused first.
+        let extras: Vec<_> = self
             .running
+            .apps()
             .iter()
             .filter(|app| {
                 !self
@@ -127,7 +256,6 @@ impl FocusApplet {
             })
             .cloned()
             .collect();
-        extras.sort();
 
         for app_id in extras {
             let key = app_id.to_lowercase();
@@ -171,15 +299,16 @@ impl FocusApplet {
             .icon_button_from_handle(cosmic::widget::icon::from_name(icon_name).handle())
             .on_press_down(Message::Activate(item.app_id.clone()));
 
-        self.core
-            .applet
-            .applet_tooltip::<Message>(
-                icon_button,
-                item.display_name.clone(),
-                false,
-                Message::Surface,
-                None,
-            )
+        let tooltip = self.core.applet.applet_tooltip::<Message>(
+            icon_button,
+            item.display_name.clone(),
+            self.menu.is_some(),
+            Message::Surface,
+            None,
+        );
+        mouse_area(tooltip)
+            .on_middle_press(Message::Summon(item.app_id.clone()))
+            .on_right_press(Message::OpenMenu(item.app_id.clone()))
             .into()
     }
 
@@ -190,17 +319,28 @@ impl FocusApplet {
             .iter()
             .filter(|id| !id.is_empty())
             .take(10)
-            .cloned()
+            .map(|id| self.shortcut_command(id))
             .collect();
 
-        if targets == self.shortcut_targets {
+        let previous = self
+            .focus_config
+            .previous_shortcut
+            .clone()
+            .filter(|binding| !binding.is_empty());
+
+        if targets == self.shortcut_targets && previous == self.previous_binding {
             return;
         }
 
-        if let Err(err) = apply_super_shortcuts(&targets) {
-            log::error!("Failed to update Super+number shortcuts: {err}");
+        if let Err(err) = apply_shortcuts(
+            &targets,
+            self.previous_binding.as_deref(),
+            previous.as_deref(),
+        ) {
+            log::error!("Failed to update shortcuts: {err}");
         } else {
             self.shortcut_targets = targets;
+            self.previous_binding = previous;
         }
     }
 }
@@ -215,23 +355,20 @@ impl cosmic::Application for FocusApplet {
         let mut applet = Self {
             core,
             config: Self::load_config(),
-            running: Vec::new(),
+            focus_config: Self::load_focus_config(),
+            running: focus::RunningApps::default(),
             items: Vec::new(),
             locales: get_languages_from_env(),
             desktop_entries: Vec::new(),
             desktop_cache: FxHashMap::default(),
             shortcut_targets: Vec::new(),
+            previous_binding: None,
+            token_tx: None,
+            menu: None,
+            summoning: BTreeSet::new(),
         };
         applet.update_desktop_entries();
         applet.rebuild_items();
-        applet.running = match focus::list_running_apps() {
-            Ok(apps) => apps,
-            Err(err) => {
-                log::error!("Failed to list running apps: {err}");
-                Vec::new()
-            }
-        };
-        applet.rebuild_items();
         applet.update_shortcut_bindings();
         (applet, Task::none())
     }
@@ -251,15 +388,45 @@ impl cosmic::Application for FocusApplet {
     fn update(&mut self, message: Message) -> app::Task<Message> {
         match message {
             Message::Activate(app_id) => {
-                if let Err(err) = focus::focus_or_launch(&app_id, None) {
-                    log::error!("Failed to focus {app_id}: {err}");
+                self.request_token(app_id, false);
+                Task::none()
+            }
+            Message::Summon(app_id) => {
+                self.request_token(app_id, true);
+                Task::none()
+            }
+            Message::OpenMenu(app_id) => {
+                if let Some((id, _)) = self.menu.take() {
+                    return destroy_popup(id);
+                }
+                let Some(parent) = self.core.main_window_id() else {
+                    return Task::none();
+                };
+                let id = window::Id::unique();
+                self.menu = Some((id, app_id));
+                get_popup(
+                    self.core
+                        .applet
+                        .get_popup_settings(parent, id, None, None, None),
+                )
+            }
+            Message::MenuClosed(id) => {
+                if self.menu.as_ref().is_some_and(|(menu, _)| *menu == id) {
+                    self.menu = None;
                 }
                 Task::none()
             }
-            Message::AppsUpdated(apps) => {
-                self.running = apps;
-                self.rebuild_items();
-                self.update_shortcut_bindings();
+            Message::Quit(app_id) => {
+                self.quit(app_id);
+                match self.menu.take() {
+                    Some((id, _)) => destroy_popup(id),
+                    None => Task::none(),
+                }
+            }
+            Message::Toplevel(event) => {
+                if self.running.apply(&event) {
+                    self.rebuild_items();
+                }
                 Task::none()
             }
             Message::ConfigUpdated(config) => {
@@ -268,9 +435,25 @@ impl cosmic::Application for FocusApplet {
                 self.update_shortcut_bindings();
                 Task::none()
             }
+            Message::FocusConfigUpdated(config) => {
+                self.focus_config = config;
+                self.update_shortcut_bindings();
+                Task::none()
+            }
             Message::Surface(action) => {
                 cosmic::task::message(Action::Cosmic(cosmic::app::Action::Surface(action)))
             }
+            Message::Token(update) => {
+                match update {
+                    TokenUpdate::Init(tx) => self.token_tx = Some(tx),
+                    TokenUpdate::Finished => self.token_tx = None,
+                    TokenUpdate::ActivationToken { token, exec, .. } => {
+                        let summon = self.summoning.remove(&exec);
+                        self.focus_or_launch(&exec, token, summon);
+                    }
+                }
+                Task::none()
+            }
         }
     }
 
@@ -281,7 +464,18 @@ impl cosmic::Application for FocusApplet {
             }
             Message::ConfigUpdated(update.config)
         });
-        Subscription::batch(vec![running_apps_subscription(), config])
+        let focus_config = self.core.watch_config(APP_ID).map(|update| {
+            for err in update.errors {
+                log::warn!("Focus config watch error: {err}");
+            }
+            Message::FocusConfigUpdated(update.config)
+        });
+        Subscription::batch(vec![
+            running_apps_subscription(),
+            config,
+            focus_config,
+            activation_token_subscription(0).map(Message::Token),
+        ])
     }
 
     fn view(&self) -> Element<'_, Message> {
@@ -293,6 +487,22 @@ impl cosmic::Application for FocusApplet {
 
         container(row).width(iced::Length::Shrink).into()
     }
+
+    fn view_window(&self, id: window::Id) -> Element<'_, Message> {
+        let Some((_, app_id)) = self.menu.as_ref().filter(|(menu, _)| *menu == id) else {
+            return text("").into();
+        };
+        let quit =
+            cosmic::applet::menu_button(text::body("Quit")).on_press(Message::Quit(app_id.clone()));
+        self.core
+            .applet
+            .popup_container(Column::new().push(quit).padding([8, 0]))
+            .into()
+    }
+
+    fn on_close_requested(&self, id: window::Id) -> Option<Message> {
+        Some(Message::MenuClosed(id))
+    }
 }
 
 pub fn run() -> cosmic::iced::Result {
@@ -304,15 +514,16 @@ fn running_apps_subscription() -> Subscription<Message> {
     Subscription::run_with_id(
         TypeId::of::<RunningAppsSubscription>(),
         stream::channel(16, |mut output| async move {
-            let (tx, rx) = mpsc::channel();
-            thread::spawn(move || {
-                if let Err(err) = focus::watch_running_apps(tx) {
-                    log::error!("Wayland watcher exited: {err}");
+            let mut events = match focus::toplevel_stream(focus::DEFAULT_DISCOVERY_TIMEOUT).await {
+                Ok(events) => events,
+                Err(err) => {
+                    log::error!("Failed to watch running apps: {err}");
+                    return;
                 }
-            });
+            };
 
-            while let Ok(apps) = rx.recv() {
-                if output.send(Message::AppsUpdated(apps)).await.is_err() {
+            while let Some(event) = events.next().await {
+                if output.send(Message::Toplevel(event)).await.is_err() {
                     break;
                 }
             }
@@ -320,7 +531,41 @@ fn running_apps_subscription() -> Subscription<Message> {
     )
 }
 
-fn apply_super_shortcuts(targets: &[String]) -> anyhow::Result<()> {
+/// Runs focus work, which connects to the compositor and waits for it, on its own thread so
+/// the panel keeps responding meanwhile.
+fn spawn_blocking(work: impl FnOnce() + Send + 'static) {
+    thread::spawn(work);
+}
+
+/// Quotes an argument for the `sh -c` command line of a spawn shortcut.
+fn shell_quote(arg: &str) -> String {
+    if !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.+/:@".contains(c))
+    {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+/// The command the `previous` shortcut spawns.
+const PREVIOUS_COMMAND: &str = "cosmic-app-focus previous";
+
+/// Whether `action` is the `previous` shortcut this applet writes.
+fn is_previous_action(action: Option<&ShortcutAction>) -> bool {
+    matches!(action, Some(ShortcutAction::Spawn(command)) if command == PREVIOUS_COMMAND)
+}
+
+/// Binds Super+1…0 to `commands` and `previous` to [`PREVIOUS_COMMAND`], dropping the
+/// `replaced` binding a former `previous` setting left behind. Keys the user bound to
+/// something else are left alone.
+fn apply_shortcuts(
+    commands: &[String],
+    replaced: Option<&str>,
+    previous: Option<&str>,
+) -> anyhow::Result<()> {
     let context = ShortcutConfig::context()?;
     let mut entry = ShortcutConfig::get_entry(&context).unwrap_or_default();
 
@@ -335,7 +580,13 @@ fn apply_super_shortcuts(targets: &[String]) -> anyhow::Result<()> {
         }
     }
 
-    for (idx, app_id) in targets.iter().enumerate().take(10) {
+    if let Some(Ok(binding)) = replaced.map(Binding::from_str) {
+        if is_previous_action(entry.custom.0.get(&binding)) {
+            entry.custom.0.remove(&binding);
+        }
+    }
+
+    for (idx, command) in commands.iter().enumerate().take(10) {
         let key = if idx == 9 {
             "Super+0".to_string()
         } else {
@@ -343,10 +594,30 @@ fn apply_super_shortcuts(targets: &[String]) -> anyhow::Result<()> {
         };
         let binding = Binding::from_str(&key)
             .map_err(|err| anyhow::anyhow!("invalid binding {}: {}", key, err))?;
-        entry.custom.0.insert(
-            binding,
-            ShortcutAction::Spawn(format!("cosmic-app-focus {}", app_id)),
-        );
+        entry
+            .custom
+            .0
+            .insert(binding, ShortcutAction::Spawn(command.clone()));
+    }
+
+    if let Some(key) = previous {
+        let binding = Binding::from_str(key)
+            .map_err(|err| anyhow::anyhow!("invalid binding {}: {}", key, err))?;
+        match entry.custom.0.get(&binding) {
+            Some(action) if !is_previous_action(Some(action)) => {
+                log::warn!(
+                    "{} is already bound to {:?}; not binding it to 'previous'",
+                    key,
+                    action
+                );
+            }
+            _ => {
+                entry
+                    .custom
+                    .0
+                    .insert(binding, ShortcutAction::Spawn(PREVIOUS_COMMAND.to_string()));
+            }
+        }
     }
 
     entry.write_entry(&context)?;
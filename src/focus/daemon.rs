@@ -0,0 +1,569 @@
+use futures::{channel::oneshot, executor::block_on};
+use serde::{Deserialize, Serialize};
+use smithay_client_toolkit::reexports::{
+    calloop::{
+        channel::{self, Channel},
+        generic::Generic,
+        timer::{TimeoutAction, Timer},
+        EventLoop, Interest, LoopHandle, Mode, PostAction,
+    },
+    calloop_wayland_source::WaylandSource,
+};
+use std::{
+    env, fs,
+    io::{self, BufRead, BufReader, ErrorKind, Read, Write},
+    mem,
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use wayland_client::Connection;
+
+use super::{
+    activate_launched, close_with, focus_with, kill_grace, kill_remaining, minimize_with,
+    new_window, previous_with,
+    service::{FocusService, ServiceRequest, BUS_NAME, OBJECT_PATH},
+    Acted, AppMatcher, FocusError, FocusErrorKind, FocusOptions, FocusOutcome, Toplevel,
+    ToplevelOrder, ToplevelTracker,
+};
+
+const SOCKET_NAME: &str = "cosmic-app-focus.sock";
+/// How long the daemon waits for a connected client to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+/// Longest request line the daemon accepts.
+const MAX_REQUEST_LEN: usize = 64 * 1024;
+/// How long a client waits for the daemon's answer, on top of any `--wait`.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// One JSON line sent by a client.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Request {
+    Focus {
+        app_id: String,
+        options: Box<FocusOptions>,
+    },
+    /// Switch back to the previously focused app.
+    Previous,
+    /// Every window, in `order`.
+    List { order: ToplevelOrder },
+}
+
+/// One JSON line sent back by the daemon.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Response {
+    Done(FocusOutcome),
+    Toplevels(Vec<Toplevel>),
+    Failed {
+        kind: FocusErrorKind,
+        message: String,
+    },
+}
+
+impl From<Result<FocusOutcome, FocusError>> for Response {
+    fn from(result: Result<FocusOutcome, FocusError>) -> Self {
+        match result {
+            Ok(outcome) => Response::Done(outcome),
+            Err(err) => Response::Failed {
+                kind: err.kind(),
+                message: err.to_string(),
+            },
+        }
+    }
+}
+
+/// `$XDG_RUNTIME_DIR/cosmic-app-focus.sock`, where the daemon listens.
+pub fn socket_path() -> Option<PathBuf> {
+    env::var_os("XDG_RUNTIME_DIR").map(|dir| PathBuf::from(dir).join(SOCKET_NAME))
+}
+
+/// Hands a focus request to a running daemon.
+///
+/// Returns `None` when no daemon is listening, in which case the caller should fall back to
+/// [`focus_or_launch`](super::focus_or_launch).
+pub fn forward_to_daemon(
+    app_id: &str,
+    options: &FocusOptions,
+) -> Option<Result<FocusOutcome, FocusError>> {
+    let stream = UnixStream::connect(socket_path()?).ok()?;
+    log::debug!("Forwarding '{}' to the focus daemon", app_id);
+    let request = Request::Focus {
+        app_id: app_id.to_string(),
+        options: Box::new(options.clone()),
+    };
+    let timeout = options.wait.unwrap_or_default() + RESPONSE_TIMEOUT;
+    Some(exchange(&stream, &request, timeout).and_then(Response::outcome))
+}
+
+/// Asks a running daemon to switch back to the previously focused app, or returns `None`
+/// when no daemon is listening.
+pub(crate) fn forward_previous() -> Option<Result<FocusOutcome, FocusError>> {
+    let stream = UnixStream::connect(socket_path()?).ok()?;
+    Some(exchange(&stream, &Request::Previous, RESPONSE_TIMEOUT).and_then(Response::outcome))
+}
+
+/// Asks a running daemon for every window in `order`, or returns `None` when no daemon is
+/// listening.
+///
+/// Unlike [`list_toplevels`](super::list_toplevels), the daemon has seen focus change since
+/// it started, so [`ToplevelOrder::Recent`] covers every window it saw focused.
+pub fn forward_list(order: ToplevelOrder) -> Option<Result<Vec<Toplevel>, FocusError>> {
+    let stream = UnixStream::connect(socket_path()?).ok()?;
+    log::debug!("Asking the focus daemon for its windows");
+    let request = Request::List { order };
+    Some(
+        exchange(&stream, &request, RESPONSE_TIMEOUT).and_then(|response| match response {
+            Response::Toplevels(toplevels) => Ok(toplevels),
+            _ => Err(Response::unexpected()),
+        }),
+    )
+}
+
+/// Sends `request` and reads the answer, turning a reported failure into an error.
+fn exchange(
+    stream: &UnixStream,
+    request: &Request,
+    timeout: Duration,
+) -> Result<Response, FocusError> {
+    stream
+        .set_read_timeout(Some(timeout))
+        .map_err(FocusError::Daemon)?;
+    write_line(stream, request).map_err(FocusError::Daemon)?;
+
+    let mut line = String::new();
+    BufReader::new(stream)
+        .read_line(&mut line)
+        .map_err(FocusError::Daemon)?;
+    match serde_json::from_str(&line).map_err(|err| FocusError::Daemon(err.into()))? {
+        Response::Failed { kind, message } => Err(FocusError::Remote { kind, message }),
+        response => Ok(response),
+    }
+}
+
+impl Response {
+    fn outcome(self) -> Result<FocusOutcome, FocusError> {
+        match self {
+            Response::Done(outcome) => Ok(outcome),
+            _ => Err(Response::unexpected()),
+        }
+    }
+
+    /// An answer that does not fit the request, e.g. from a daemon of another version.
+    fn unexpected() -> FocusError {
+        FocusError::Daemon(io::Error::new(
+            ErrorKind::InvalidData,
+            "unexpected answer from the focus daemon",
+        ))
+    }
+}
+
+/// Reads what a client has sent so far into `buf`, returning its request once the line is
+/// complete.
+fn read_request(mut stream: &UnixStream, buf: &mut Vec<u8>) -> io::Result<Option<String>> {
+    let mut chunk = [0; 4096];
+    loop {
+        match stream.read(&mut chunk) {
+            Ok(0) => {
+                return Err(io::Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "hung up before sending a request",
+                ))
+            }
+            Ok(read) => buf.extend_from_slice(&chunk[..read]),
+            Err(err) if err.kind() == ErrorKind::WouldBlock => return Ok(None),
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+        if let Some(end) = buf.iter().position(|&byte| byte == b'\n') {
+            buf.truncate(end);
+            return String::from_utf8(mem::take(buf))
+                .map(Some)
+                .map_err(|err| io::Error::new(ErrorKind::InvalidData, err));
+        }
+        if buf.len() > MAX_REQUEST_LEN {
+            return Err(io::Error::new(ErrorKind::InvalidData, "request too long"));
+        }
+    }
+}
+
+fn write_line<T: Serialize>(mut stream: &UnixStream, value: &T) -> io::Result<()> {
+    let mut line = serde_json::to_string(value)?;
+    line.push('\n');
+    stream.write_all(line.as_bytes())
+}
+
+/// Where the answer to a focus request goes.
+enum Client {
+    Socket(UnixStream),
+    Bus(oneshot::Sender<Result<FocusOutcome, FocusError>>),
+}
+
+impl Client {
+    fn reply(self, result: Result<FocusOutcome, FocusError>) {
+        match self {
+            Client::Socket(stream) => {
+                if let Err(err) = write_line(&stream, &Response::from(result)) {
+                    log::debug!("Failed to answer focus daemon client: {err}");
+                }
+            }
+            Client::Bus(reply) => {
+                let _ = reply.send(result);
+            }
+        }
+    }
+}
+
+/// A `--wait` request whose app has been launched but has not shown a window yet.
+struct PendingLaunch {
+    id: u64,
+    app_id: String,
+    matcher: AppMatcher,
+    client: Client,
+}
+
+struct Daemon {
+    conn: Connection,
+    tracker: ToplevelTracker,
+    handle: LoopHandle<'static, Daemon>,
+    bus: Option<zbus::blocking::Connection>,
+    pending: Vec<PendingLaunch>,
+    next_pending: u64,
+    /// The window list last sent with `ToplevelsChanged`.
+    announced: Vec<Toplevel>,
+}
+
+impl Daemon {
+    fn accept(&mut self, listener: &UnixListener) -> io::Result<()> {
+        loop {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    if let Err(err) = self.watch_client(stream) {
+                        log::warn!("Dropped focus daemon client: {err}");
+                    }
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Collects the client's request line as it arrives, without holding up the event loop,
+    /// and serves it once complete. Clients that have not sent a request within
+    /// [`REQUEST_TIMEOUT`] are dropped.
+    fn watch_client(&mut self, stream: UnixStream) -> io::Result<()> {
+        stream.set_nonblocking(true)?;
+        let mut request = Vec::new();
+        let token = self
+            .handle
+            .insert_source(
+                Generic::new(stream, Interest::READ, Mode::Level),
+                move |_, stream, daemon| {
+                    match read_request(stream, &mut request) {
+                        Ok(None) => return Ok(PostAction::Continue),
+                        Ok(Some(line)) => {
+                            let stream = stream.try_clone()?;
+                            stream.set_nonblocking(false)?;
+                            daemon.serve(stream, &line);
+                        }
+                        Err(err) => log::warn!("Dropped focus daemon client: {err}"),
+                    }
+                    Ok(PostAction::Remove)
+                },
+            )
+            .map_err(|err| io::Error::other(err.error))?;
+        self.handle
+            .insert_source(
+                Timer::from_duration(REQUEST_TIMEOUT),
+                move |_, _, daemon| {
+                    // Does nothing when the request was already served.
+                    daemon.handle.remove(token);
+                    TimeoutAction::Drop
+                },
+            )
+            .map_err(|err| io::Error::other(err.error))?;
+        Ok(())
+    }
+
+    fn serve(&mut self, stream: UnixStream, line: &str) {
+        match serde_json::from_str(line) {
+            Ok(Request::Focus { app_id, options }) => {
+                self.focus(Client::Socket(stream), app_id, *options)
+            }
+            Ok(Request::Previous) => Client::Socket(stream)
+                .reply(previous_with(&self.conn, &self.tracker).map(|_| FocusOutcome::Focused)),
+            Ok(Request::List { order }) => {
+                let toplevels = Response::Toplevels(self.tracker.toplevels_by(order));
+                if let Err(err) = write_line(&stream, &toplevels) {
+                    log::debug!("Failed to answer focus daemon client: {err}");
+                }
+            }
+            Err(err) => Client::Socket(stream).reply(Err(FocusError::Daemon(io::Error::new(
+                ErrorKind::InvalidData,
+                err,
+            )))),
+        }
+    }
+
+    fn handle_service(&mut self, request: ServiceRequest) {
+        match request {
+            ServiceRequest::FocusOrLaunch {
+                app_id,
+                options,
+                reply,
+            } => self.focus(Client::Bus(reply), app_id, options),
+            ServiceRequest::FocusPrevious { reply } => {
+                let _ = reply.send(previous_with(&self.conn, &self.tracker));
+            }
+            ServiceRequest::ListToplevels { order, reply } => {
+                let _ = reply.send(self.tracker.toplevels_by(order));
+            }
+            ServiceRequest::Close {
+                app_id,
+                options,
+                reply,
+            } => {
+                let result = self.with_matcher(&app_id, &options, |conn, tracker, matcher| {
+                    close_with(conn, tracker, matcher, &options)
+                });
+                if let Ok(closed) = &result {
+                    if let Some(grace) = kill_grace(&options, closed) {
+                        self.kill_after(app_id, closed.clone(), grace);
+                    }
+                }
+                let _ = reply.send(result.map(|closed| closed.len()));
+            }
+            ServiceRequest::Minimize {
+                app_id,
+                options,
+                reply,
+            } => {
+                let _ = reply.send(self.with_matcher(&app_id, &options, minimize_with));
+            }
+        }
+    }
+
+    fn with_matcher<T>(
+        &self,
+        app_id: &str,
+        options: &FocusOptions,
+        action: impl FnOnce(&Connection, &ToplevelTracker, &AppMatcher) -> Result<T, FocusError>,
+    ) -> Result<T, FocusError> {
+        let matcher = AppMatcher::for_target(app_id, options)?;
+        action(&self.conn, &self.tracker, &matcher)
+    }
+
+    /// Terminates the app if any of its windows in `closed` is still open once `grace` is
+    /// over.
+    fn kill_after(&self, app_id: String, closed: Vec<u64>, grace: Duration) {
+        let timer = self
+            .handle
+            .insert_source(Timer::from_duration(grace), move |_, _, daemon| {
+                kill_remaining(&daemon.tracker, &app_id, &closed);
+                TimeoutAction::Drop
+            });
+        if let Err(err) = timer {
+            log::warn!("Cannot schedule terminating unclosed windows: {}", err);
+        }
+    }
+
+    fn focus(&mut self, client: Client, app_id: String, options: FocusOptions) {
+        log::debug!("Daemon request to focus '{}'", app_id);
+        let matcher = match AppMatcher::for_target(&app_id, &options) {
+            Ok(matcher) => matcher,
+            Err(err) => return client.reply(Err(err)),
+        };
+
+        match focus_with(&self.conn, &self.tracker, &matcher, &app_id, &options) {
+            Ok(Acted::Focused) => return client.reply(Ok(FocusOutcome::Focused)),
+            Ok(Acted::Minimized) => return client.reply(Ok(FocusOutcome::Minimized)),
+            Ok(Acted::Launched) => {}
+            Err(err) => return client.reply(Err(err)),
+        }
+        let Some(timeout) = options.wait else {
+            return client.reply(Ok(FocusOutcome::Launched));
+        };
+
+        let id = self.next_pending;
+        self.next_pending += 1;
+        let timer =
+            self.handle
+                .insert_source(Timer::from_duration(timeout), move |_, _, daemon| {
+                    if let Some(idx) = daemon.pending.iter().position(|pending| pending.id == id) {
+                        let pending = daemon.pending.swap_remove(idx);
+                        pending.client.reply(Err(FocusError::NeverAppeared {
+                            app_id: pending.app_id,
+                            timeout,
+                        }));
+                    }
+                    TimeoutAction::Drop
+                });
+        if let Err(err) = timer {
+            log::warn!("Cannot time out the wait for '{}': {}", app_id, err);
+        }
+        self.pending.push(PendingLaunch {
+            id,
+            app_id,
+            matcher,
+            client,
+        });
+        self.resolve_pending();
+    }
+
+    /// Activates the windows launched apps have opened since the last dispatch.
+    fn resolve_pending(&mut self) {
+        let mut idx = 0;
+        while idx < self.pending.len() {
+            let pending = &self.pending[idx];
+            match new_window(&self.tracker, &pending.matcher, &[]) {
+                Some(handle) => {
+                    let pending = self.pending.swap_remove(idx);
+                    let result =
+                        activate_launched(&self.conn, &self.tracker, &pending.app_id, &handle)
+                            .map(|()| FocusOutcome::Launched);
+                    pending.client.reply(result);
+                }
+                None => idx += 1,
+            }
+        }
+    }
+
+    /// Broadcasts the window list on D-Bus when it differs from the last one broadcast.
+    fn announce_changes(&mut self) {
+        if !self.tracker.take_changed() {
+            return;
+        }
+        let Some(bus) = self.bus.as_ref() else {
+            return;
+        };
+        let toplevels = self.tracker.toplevels();
+        if toplevels == self.announced {
+            return;
+        }
+        let emitted = bus
+            .object_server()
+            .interface::<_, FocusService>(OBJECT_PATH)
+            .and_then(|service| {
+                block_on(FocusService::toplevels_changed(
+                    service.signal_emitter(),
+                    toplevels.clone(),
+                ))
+            });
+        match emitted {
+            Ok(()) => self.announced = toplevels,
+            Err(err) => log::warn!("Failed to emit ToplevelsChanged: {err}"),
+        }
+    }
+}
+
+/// Publishes [`FocusService`] on the session bus; its calls arrive on the returned channel.
+fn serve_bus() -> zbus::Result<(zbus::blocking::Connection, Channel<ServiceRequest>)> {
+    let (requests, channel) = channel::channel();
+    let bus = zbus::blocking::connection::Builder::session()?
+        .name(BUS_NAME)?
+        .serve_at(OBJECT_PATH, FocusService { requests })?
+        .build()?;
+    Ok((bus, channel))
+}
+
+/// Binds the daemon socket, replacing a stale one but refusing to steal a live one.
+fn bind_socket(path: &Path) -> Result<UnixListener, FocusError> {
+    if UnixStream::connect(path).is_ok() {
+        return Err(FocusError::Daemon(io::Error::new(
+            ErrorKind::AddrInUse,
+            format!("another daemon is listening on {}", path.display()),
+        )));
+    }
+    let _ = fs::remove_file(path);
+    let listener = UnixListener::bind(path).map_err(FocusError::Daemon)?;
+    listener.set_nonblocking(true).map_err(FocusError::Daemon)?;
+    Ok(listener)
+}
+
+/// Keeps one discovered [`ToplevelTracker`] alive and serves focus requests from
+/// [`forward_to_daemon`] on [`socket_path`], and the `com.system76.CosmicAppFocus1` D-Bus
+/// service when the session bus is available, until the compositor goes away.
+///
+/// The initial discovery waits up to `discovery_timeout` for the windows to be described.
+pub fn run_daemon(discovery_timeout: Duration) -> Result<(), FocusError> {
+    let path = socket_path().ok_or_else(|| {
+        FocusError::Daemon(io::Error::new(
+            ErrorKind::NotFound,
+            "XDG_RUNTIME_DIR is not set",
+        ))
+    })?;
+
+    let (conn, mut event_queue, mut tracker) = ToplevelTracker::connect()?;
+    tracker.discover(&mut event_queue, discovery_timeout)?;
+    tracker.take_changed();
+    let listener = bind_socket(&path)?;
+    log::info!("Focus daemon listening on {}", path.display());
+
+    let result = serve(conn, event_queue, tracker, listener);
+    let _ = fs::remove_file(&path);
+    result
+}
+
+fn serve(
+    conn: Connection,
+    event_queue: wayland_client::EventQueue<ToplevelTracker>,
+    tracker: ToplevelTracker,
+    listener: UnixListener,
+) -> Result<(), FocusError> {
+    let mut event_loop: EventLoop<'static, Daemon> =
+        EventLoop::try_new().map_err(|err| FocusError::Daemon(err.into()))?;
+    let handle = event_loop.handle();
+
+    handle
+        .insert_source(
+            WaylandSource::new(conn.clone(), event_queue),
+            |_, queue, daemon| {
+                let dispatched = queue.dispatch_pending(&mut daemon.tracker);
+                daemon.resolve_pending();
+                daemon.announce_changes();
+                dispatched
+            },
+        )
+        .map_err(|err| FocusError::Daemon(io::Error::other(err.error)))?;
+    handle
+        .insert_source(
+            Generic::new(listener, Interest::READ, Mode::Level),
+            |_, listener, daemon| {
+                daemon.accept(listener)?;
+                Ok(PostAction::Continue)
+            },
+        )
+        .map_err(|err| FocusError::Daemon(io::Error::other(err.error)))?;
+
+    let bus = match serve_bus() {
+        Ok((bus, requests)) => {
+            handle
+                .insert_source(requests, |event, _, daemon| {
+                    if let channel::Event::Msg(request) = event {
+                        daemon.handle_service(request);
+                    }
+                })
+                .map_err(|err| FocusError::Daemon(io::Error::other(err.error)))?;
+            log::info!("Serving {} on the session bus", BUS_NAME);
+            Some(bus)
+        }
+        Err(err) => {
+            log::warn!("D-Bus service unavailable: {err}");
+            None
+        }
+    };
+
+    let announced = tracker.toplevels();
+    let mut daemon = Daemon {
+        conn,
+        tracker,
+        handle,
+        bus,
+        pending: Vec::new(),
+        next_pending: 0,
+        announced,
+    };
+    event_loop
+        .run(None, &mut daemon, |_| {})
+        .map_err(|err| FocusError::Daemon(err.into()))
+}
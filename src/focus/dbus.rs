@@ -0,0 +1,28 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use zbus::{blocking::Connection, zvariant::Value};
+
+#[zbus::proxy(interface = "org.freedesktop.Application")]
+trait Application {
+    fn activate(&self, platform_data: HashMap<&str, Value<'_>>) -> zbus::Result<()>;
+}
+
+/// Starts (or raises) a `DBusActivatable` app through `org.freedesktop.Application.Activate`
+/// on the bus name matching its desktop file id.
+pub(crate) fn activate_application(app_id: &str, activation_token: Option<&str>) -> Result<()> {
+    let conn = Connection::session().context("connect to session bus")?;
+    let path = format!("/{}", app_id.replace('.', "/").replace('-', "_"));
+    let app = ApplicationProxyBlocking::builder(&conn)
+        .destination(app_id)?
+        .path(path)?
+        .build()
+        .context("build org.freedesktop.Application proxy")?;
+
+    let mut platform_data = HashMap::new();
+    if let Some(token) = activation_token {
+        platform_data.insert("activation-token", Value::from(token));
+        platform_data.insert("desktop-startup-id", Value::from(token));
+    }
+    app.activate(platform_data)
+        .with_context(|| format!("activate {app_id} over D-Bus"))
+}
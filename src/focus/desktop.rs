@@ -0,0 +1,11 @@
+use cosmic::desktop::fde::{self, get_languages_from_env, DesktopEntry};
+
+/// Resolves a desktop entry by desktop file id (with or without the `.desktop` suffix).
+pub fn find_desktop_entry(app_id: &str) -> Option<DesktopEntry> {
+    let app_id = app_id.strip_suffix(".desktop").unwrap_or(app_id);
+    let locales = get_languages_from_env();
+    let entries: Vec<DesktopEntry> = fde::Iter::new(fde::default_paths())
+        .filter_map(|path| DesktopEntry::from_path(path, Some(&locales)).ok())
+        .collect();
+    fde::find_app_by_id(&entries, fde::unicase::Ascii::new(app_id)).cloned()
+}
@@ -0,0 +1,132 @@
+use serde::{Deserialize, Serialize};
+use std::{error::Error, fmt, io, time::Duration};
+
+use wayland_client::ConnectError;
+
+/// Why a focus operation failed.
+#[derive(Debug)]
+pub enum FocusError {
+    /// No Wayland compositor could be reached.
+    NoCompositor(ConnectError),
+    /// The compositor does not offer a protocol the operation needs.
+    MissingProtocol(&'static str),
+    /// The match pattern is not valid for its [`MatchMode`](super::MatchMode).
+    InvalidPattern {
+        pattern: String,
+        source: regex::Error,
+    },
+    /// The app had no matching window and could not be started.
+    LaunchFailed {
+        app_id: String,
+        source: anyhow::Error,
+    },
+    /// The app was launched but no matching window appeared within the wait timeout.
+    NeverAppeared { app_id: String, timeout: Duration },
+    /// Communication with the compositor broke down.
+    Wayland {
+        context: &'static str,
+        source: Box<dyn Error + Send + Sync>,
+    },
+    /// The focus daemon's socket could not be served or talked to.
+    Daemon(io::Error),
+    /// No other app has been focused since the focus history started.
+    NoHistory,
+    /// The focus daemon handled the request and reported this failure.
+    Remote {
+        kind: FocusErrorKind,
+        message: String,
+    },
+}
+
+/// The category of a [`FocusError`], which survives being sent over the daemon socket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FocusErrorKind {
+    NoCompositor,
+    MissingProtocol,
+    InvalidPattern,
+    LaunchFailed,
+    NeverAppeared,
+    Wayland,
+    Daemon,
+    NoHistory,
+}
+
+impl FocusError {
+    pub fn kind(&self) -> FocusErrorKind {
+        match self {
+            FocusError::NoCompositor(_) => FocusErrorKind::NoCompositor,
+            FocusError::MissingProtocol(_) => FocusErrorKind::MissingProtocol,
+            FocusError::InvalidPattern { .. } => FocusErrorKind::InvalidPattern,
+            FocusError::LaunchFailed { .. } => FocusErrorKind::LaunchFailed,
+            FocusError::NeverAppeared { .. } => FocusErrorKind::NeverAppeared,
+            FocusError::Wayland { .. } => FocusErrorKind::Wayland,
+            FocusError::Daemon(_) => FocusErrorKind::Daemon,
+            FocusError::NoHistory => FocusErrorKind::NoHistory,
+            FocusError::Remote { kind, .. } => *kind,
+        }
+    }
+
+    /// Adapter for `map_err` that wraps a Wayland-level failure with what was being done.
+    pub(crate) fn wayland<E>(context: &'static str) -> impl FnOnce(E) -> Self
+    where
+        E: Into<Box<dyn Error + Send + Sync>>,
+    {
+        move |source| FocusError::Wayland {
+            context,
+            source: source.into(),
+        }
+    }
+}
+
+impl fmt::Display for FocusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FocusError::NoCompositor(err) => write!(f, "cannot connect to Wayland: {err}"),
+            FocusError::MissingProtocol(protocol) => {
+                write!(f, "compositor does not support {protocol}")
+            }
+            FocusError::InvalidPattern { pattern, source } => {
+                write!(f, "invalid pattern '{pattern}': {source}")
+            }
+            FocusError::LaunchFailed { app_id, source } => {
+                write!(f, "failed to launch '{app_id}': {source:#}")
+            }
+            FocusError::NeverAppeared { app_id, timeout } => {
+                write!(f, "'{app_id}' did not open a window within {timeout:?}")
+            }
+            FocusError::Wayland { context, source } => write!(f, "{context}: {source}"),
+            FocusError::Daemon(err) => write!(f, "focus daemon: {err}"),
+            FocusError::NoHistory => f.write_str("no previously focused app to switch back to"),
+            FocusError::Remote { message, .. } => f.write_str(message),
+        }
+    }
+}
+
+impl Error for FocusError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            FocusError::NoCompositor(err) => Some(err),
+            FocusError::InvalidPattern { source, .. } => Some(source),
+            FocusError::LaunchFailed { source, .. } => Some(source.as_ref()),
+            FocusError::Wayland { source, .. } => Some(source.as_ref()),
+            FocusError::Daemon(err) => Some(err),
+            FocusError::MissingProtocol(_)
+            | FocusError::NeverAppeared { .. }
+            | FocusError::NoHistory
+            | FocusError::Remote { .. } => None,
+        }
+    }
+}
+
+/// What [`focus_or_launch`](super::focus_or_launch) did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FocusOutcome {
+    /// An existing window was activated.
+    Focused,
+    /// No window matched, so the app was started.
+    Launched,
+    /// The app already had focus and was minimized.
+    Minimized,
+}
@@ -0,0 +1,191 @@
+use serde::{Deserialize, Serialize};
+
+use super::Toplevel;
+
+/// A change to the compositor's windows, as sent by [`watch_toplevels`](super::watch_toplevels).
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToplevelEvent {
+    /// A window opened.
+    Added(Toplevel),
+    /// A window closed; this is its last known state.
+    Removed(Toplevel),
+    /// A window's app id, title, states, outputs or workspaces changed, other than it gaining
+    /// focus.
+    Updated(Toplevel),
+    /// A window gained focus.
+    Activated(Toplevel),
+    /// The connection to the compositor broke and every window is gone with it. Once it is
+    /// back, each window is announced again with a new id.
+    Disconnected,
+}
+
+/// The events that turn `old` into `new`: removals first, then additions and updates in
+/// window order, and the activation last. Unchanged windows produce nothing.
+pub(crate) fn diff(old: &[Toplevel], new: &[Toplevel]) -> Vec<ToplevelEvent> {
+    let mut events: Vec<ToplevelEvent> = old
+        .iter()
+        .filter(|before| !new.iter().any(|after| after.id == before.id))
+        .cloned()
+        .map(ToplevelEvent::Removed)
+        .collect();
+
+    let mut activated = Vec::new();
+    for after in new {
+        let before = old.iter().find(|before| before.id == after.id);
+        match before {
+            None => events.push(ToplevelEvent::Added(after.clone())),
+            Some(before) => {
+                let lost_focus = before.activated && !after.activated;
+                let unfocused_change = Toplevel {
+                    activated: after.activated,
+                    ..before.clone()
+                } != *after;
+                if lost_focus || unfocused_change {
+                    events.push(ToplevelEvent::Updated(after.clone()));
+                }
+            }
+        }
+        if after.activated && !before.is_some_and(|before| before.activated) {
+            activated.push(ToplevelEvent::Activated(after.clone()));
+        }
+    }
+    events.extend(activated);
+    events
+}
+
+/// Running app ids, most recently focused first, kept up to date from [`ToplevelEvent`]s.
+#[derive(Debug, Clone, Default)]
+pub struct RunningApps {
+    windows: Vec<(u64, String)>,
+    apps: Vec<String>,
+}
+
+impl RunningApps {
+    /// Applies `event`, returning whether [`apps`](Self::apps) changed.
+    pub fn apply(&mut self, event: &ToplevelEvent) -> bool {
+        let before = self.apps.clone();
+        match event {
+            ToplevelEvent::Added(toplevel) | ToplevelEvent::Updated(toplevel) => {
+                self.set_window(toplevel);
+            }
+            ToplevelEvent::Removed(toplevel) => {
+                self.windows.retain(|(id, _)| *id != toplevel.id);
+            }
+            ToplevelEvent::Activated(toplevel) => {
+                self.set_window(toplevel);
+                self.apps
+                    .retain(|app_id| !app_id.eq_ignore_ascii_case(&toplevel.app_id));
+                if !toplevel.app_id.is_empty() {
+                    self.apps.insert(0, toplevel.app_id.clone());
+                }
+            }
+            ToplevelEvent::Disconnected => self.windows.clear(),
+        }
+
+        let windows = &self.windows;
+        self.apps.retain(|app_id| {
+            windows
+                .iter()
+                .any(|(_, window_app)| window_app.eq_ignore_ascii_case(app_id))
+        });
+        for (_, app_id) in windows {
+            if !app_id.is_empty()
+                && !self
+                    .apps
+                    .iter()
+                    .any(|known| known.eq_ignore_ascii_case(app_id))
+            {
+                self.apps.push(app_id.clone());
+            }
+        }
+        self.apps != before
+    }
+
+    pub fn apps(&self) -> &[String] {
+        &self.apps
+    }
+
+    fn set_window(&mut self, toplevel: &Toplevel) {
+        match self.windows.iter_mut().find(|(id, _)| *id == toplevel.id) {
+            Some((_, app_id)) => app_id.clone_from(&toplevel.app_id),
+            None => self.windows.push((toplevel.id, toplevel.app_id.clone())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(id: u64, app_id: &str) -> Toplevel {
+        Toplevel {
+            id,
+            app_id: app_id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn focused(toplevel: Toplevel) -> Toplevel {
+        Toplevel {
+            activated: true,
+            ..toplevel
+        }
+    }
+
+    #[test]
+    fn diff_orders_removals_changes_and_activation() {
+        let old = [focused(window(1, "firefox")), window(2, "code")];
+        let renamed = Toplevel {
+            title: "notes".to_string(),
+            ..window(2, "code")
+        };
+        let new = [focused(renamed.clone()), window(3, "cosmic-term")];
+        assert_eq!(
+            diff(&old, &new),
+            [
+                ToplevelEvent::Removed(focused(window(1, "firefox"))),
+                ToplevelEvent::Updated(focused(renamed.clone())),
+                ToplevelEvent::Added(window(3, "cosmic-term")),
+                ToplevelEvent::Activated(focused(renamed)),
+            ]
+        );
+    }
+
+    #[test]
+    fn diff_reports_focus_changes_once() {
+        let old = [focused(window(1, "firefox")), window(2, "code")];
+        let new = [window(1, "firefox"), focused(window(2, "code"))];
+        assert_eq!(
+            diff(&old, &new),
+            [
+                ToplevelEvent::Updated(window(1, "firefox")),
+                ToplevelEvent::Activated(focused(window(2, "code"))),
+            ]
+        );
+        assert!(diff(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn running_apps_follow_focus_and_windows() {
+        let mut running = RunningApps::default();
+        assert!(running.apply(&ToplevelEvent::Added(window(1, "firefox"))));
+        assert!(running.apply(&ToplevelEvent::Added(window(2, "code"))));
+        assert!(!running.apply(&ToplevelEvent::Added(window(3, "Firefox"))));
+        assert!(!running.apply(&ToplevelEvent::Added(window(4, ""))));
+        assert_eq!(running.apps(), ["firefox", "code"]);
+
+        assert!(running.apply(&ToplevelEvent::Activated(focused(window(2, "code")))));
+        assert_eq!(running.apps(), ["code", "firefox"]);
+
+        assert!(!running.apply(&ToplevelEvent::Removed(window(1, "firefox"))));
+        assert!(running.apply(&ToplevelEvent::Removed(window(3, "Firefox"))));
+        assert_eq!(running.apps(), ["code"]);
+
+        assert!(running.apply(&ToplevelEvent::Updated(window(2, "vscodium"))));
+        assert_eq!(running.apps(), ["vscodium"]);
+
+        assert!(running.apply(&ToplevelEvent::Disconnected));
+        assert!(running.apps().is_empty());
+    }
+}
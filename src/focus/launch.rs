@@ -0,0 +1,321 @@
+use anyhow::{anyhow, bail, Context, Result};
+use cosmic::desktop::fde::DesktopEntry;
+use std::{
+    env,
+    os::unix::process::CommandExt,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread,
+};
+
+use super::{dbus, desktop::find_desktop_entry, systemd, FocusOptions};
+
+/// Terminal emulators tried for `Terminal=true` entries, with the flag that precedes the
+/// command to run.
+const TERMINALS: &[(&str, &str)] = &[
+    ("cosmic-term", "-e"),
+    ("x-terminal-emulator", "-e"),
+    ("gnome-terminal", "--"),
+    ("konsole", "-e"),
+    ("xterm", "-e"),
+];
+
+/// Starts `app_id`, preferring `options.launch_cmd` when given, then its desktop entry
+/// (`options.desktop_entry` or the one found by id, through D-Bus activation when the entry
+/// supports it), then an executable of the same name.
+pub(crate) fn launch(app_id: &str, options: &FocusOptions) -> Result<()> {
+    let token = options.activation_token.as_deref();
+    if let Some(cmd) = options.launch_cmd.as_deref() {
+        log::info!("Launching '{}' via override '{}'", app_id, cmd);
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(cmd);
+        return spawn_detached(command, app_id, token);
+    }
+
+    let entry = options
+        .desktop_entry
+        .clone()
+        .or_else(|| find_desktop_entry(app_id));
+    if let Some(entry) = entry {
+        if entry.desktop_entry("DBusActivatable") == Some("true") {
+            match dbus::activate_application(&entry.appid, token) {
+                Ok(()) => {
+                    log::info!("Activated '{}' over D-Bus", entry.appid);
+                    return Ok(());
+                }
+                Err(err) => log::warn!("D-Bus activation failed, running Exec instead: {err:#}"),
+            }
+        }
+        log::info!(
+            "Launching '{}' from desktop entry {}",
+            app_id,
+            entry.path.display()
+        );
+        return spawn_detached(desktop_command(&entry)?, app_id, token);
+    }
+
+    match find_executable(app_id) {
+        Some(program) => {
+            log::info!(
+                "No desktop entry for '{}'; running {}",
+                app_id,
+                program.display()
+            );
+            spawn_detached(Command::new(program), app_id, token)
+        }
+        None => bail!("no desktop entry or executable found for '{app_id}'"),
+    }
+}
+
+/// Builds the command line described by a desktop entry's `Exec`, `TryExec`, `Path` and
+/// `Terminal` keys.
+fn desktop_command(entry: &DesktopEntry) -> Result<Command> {
+    if let Some(try_exec) = entry.desktop_entry("TryExec") {
+        if find_executable(try_exec).is_none() {
+            bail!("TryExec '{}' of {} not found", try_exec, entry.appid);
+        }
+    }
+
+    let exec = entry
+        .exec()
+        .ok_or_else(|| anyhow!("desktop entry {} has no Exec key", entry.appid))?;
+    let mut argv = expand_exec(exec, entry)?;
+    if argv.is_empty() {
+        bail!("desktop entry {} has an empty Exec key", entry.appid);
+    }
+
+    if entry.terminal() {
+        let (terminal, flag) = find_terminal()
+            .ok_or_else(|| anyhow!("{} needs a terminal but none was found", entry.appid))?;
+        argv.splice(0..0, [terminal, flag.to_string()]);
+    }
+
+    let mut command = Command::new(&argv[0]);
+    command.args(&argv[1..]);
+    if let Some(dir) = entry.desktop_entry("Path").filter(|dir| !dir.is_empty()) {
+        command.current_dir(dir);
+    }
+    Ok(command)
+}
+
+/// Splits an `Exec` value into arguments and expands its field codes. No files or URLs are
+/// passed, so `%f`, `%F`, `%u` and `%U` expand to nothing.
+fn expand_exec(exec: &str, entry: &DesktopEntry) -> Result<Vec<String>> {
+    let mut argv = Vec::new();
+    for arg in split_exec(exec)? {
+        match arg.as_str() {
+            "%f" | "%F" | "%u" | "%U" => {}
+            "%i" => {
+                if let Some(icon) = entry.icon() {
+                    argv.push("--icon".to_string());
+                    argv.push(icon.to_string());
+                }
+            }
+            _ => argv.push(expand_field_codes(&arg, entry)),
+        }
+    }
+    Ok(argv)
+}
+
+fn expand_field_codes(arg: &str, entry: &DesktopEntry) -> String {
+    let mut expanded = String::with_capacity(arg.len());
+    let mut chars = arg.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            expanded.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => expanded.push('%'),
+            Some('c') => expanded.push_str(entry.desktop_entry("Name").unwrap_or_default()),
+            Some('k') => expanded.push_str(&entry.path.to_string_lossy()),
+            // Files, URLs, the icon and deprecated codes have no value here.
+            Some(_) | None => {}
+        }
+    }
+    expanded
+}
+
+/// Tokenizes an `Exec` value following the quoting rules of the Desktop Entry spec.
+fn split_exec(exec: &str) -> Result<Vec<String>> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_arg = false;
+    let mut chars = exec.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                in_arg = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(escaped @ ('"' | '`' | '$' | '\\')) => current.push(escaped),
+                            Some(other) => {
+                                current.push('\\');
+                                current.push(other);
+                            }
+                            None => bail!("unterminated escape in Exec '{exec}'"),
+                        },
+                        Some(other) => current.push(other),
+                        None => bail!("unterminated quote in Exec '{exec}'"),
+                    }
+                }
+            }
+            ' ' | '\t' => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            other => {
+                in_arg = true;
+                current.push(other);
+            }
+        }
+    }
+    if in_arg {
+        args.push(current);
+    }
+    Ok(args)
+}
+
+fn find_terminal() -> Option<(String, &'static str)> {
+    if let Ok(terminal) = env::var("TERMINAL") {
+        if find_executable(&terminal).is_some() {
+            return Some((terminal, "-e"));
+        }
+    }
+    TERMINALS
+        .iter()
+        .find(|(name, _)| find_executable(name).is_some())
+        .map(|(name, flag)| (name.to_string(), *flag))
+}
+
+/// Resolves a program name against `PATH`; names containing a slash are taken as paths.
+fn find_executable(program: &str) -> Option<PathBuf> {
+    let is_executable = |path: &Path| {
+        use std::os::unix::fs::PermissionsExt;
+        path.metadata()
+            .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+            .unwrap_or(false)
+    };
+
+    if program.contains('/') {
+        let path = PathBuf::from(program);
+        return is_executable(&path).then_some(path);
+    }
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(program))
+        .find(|path| is_executable(path))
+}
+
+/// Spawns without waiting for the app to exit. The child gets its own process group so
+/// signals aimed at the caller (e.g. Ctrl+C in a terminal) do not reach it, and a helper
+/// thread reaps it so long-running callers such as the applet do not collect zombies.
+///
+/// An activation token is exported under both the xdg-activation and the older
+/// startup-notification variable so toolkits of either generation pick it up.
+///
+/// The child is then moved into its own systemd scope; without a user manager it simply
+/// stays in the caller's.
+fn spawn_detached(mut command: Command, app_id: &str, token: Option<&str>) -> Result<()> {
+    command.stdin(Stdio::null()).process_group(0);
+    match token {
+        Some(token) => {
+            command
+                .env("XDG_ACTIVATION_TOKEN", token)
+                .env("DESKTOP_STARTUP_ID", token);
+        }
+        None => {
+            // Never leak a token this process was started with to an unrelated launch.
+            command
+                .env_remove("XDG_ACTIVATION_TOKEN")
+                .env_remove("DESKTOP_STARTUP_ID");
+        }
+    }
+    let mut child = command
+        .spawn()
+        .with_context(|| format!("failed to launch {:?}", command.get_program()))?;
+    log::debug!("Spawned pid {}", child.id());
+    match systemd::move_to_app_scope(app_id, child.id()) {
+        Ok(unit) => log::debug!("Moved pid {} into {}", child.id(), unit),
+        Err(err) => log::info!("Keeping '{}' in the launcher's scope: {:#}", app_id, err),
+    }
+    thread::spawn(move || {
+        let _ = child.wait();
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(keys: &str) -> DesktopEntry {
+        let input = format!("[Desktop Entry]\nType=Application\n{keys}");
+        DesktopEntry::from_str(
+            "/usr/share/applications/org.example.Editor.desktop",
+            &input,
+            None::<&[&str]>,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn split_exec_honours_quotes_and_escapes() {
+        assert_eq!(
+            split_exec(r#"editor  --title "My \"notes\"" "a\\b" "\$HOME" "c:\d""#).unwrap(),
+            [
+                "editor",
+                "--title",
+                r#"My "notes""#,
+                r"a\b",
+                "$HOME",
+                r"c:\d"
+            ]
+        );
+        assert_eq!(
+            split_exec("\teditor\t\"\" x ").unwrap(),
+            ["editor", "", "x"]
+        );
+        assert!(split_exec(r#"editor "open"#).is_err());
+        assert!(split_exec(r#"editor "open\"#).is_err());
+    }
+
+    #[test]
+    fn field_codes_expand_from_the_entry() {
+        let entry = entry("Name=Editor\nIcon=accessories-text-editor\n");
+        assert_eq!(expand_field_codes("100%%", &entry), "100%");
+        assert_eq!(expand_field_codes("--class=%c", &entry), "--class=Editor");
+        assert_eq!(
+            expand_field_codes("%k", &entry),
+            "/usr/share/applications/org.example.Editor.desktop"
+        );
+        assert_eq!(expand_field_codes("--open=%u%d%", &entry), "--open=");
+    }
+
+    #[test]
+    fn expand_exec_drops_file_and_url_codes() {
+        let entry = entry("Name=Editor\nIcon=accessories-text-editor\n");
+        assert_eq!(
+            expand_exec("editor %i --name %c %F %u -- %%U", &entry).unwrap(),
+            [
+                "editor",
+                "--icon",
+                "accessories-text-editor",
+                "--name",
+                "Editor",
+                "--",
+                "%U"
+            ]
+        );
+    }
+
+    #[test]
+    fn expand_exec_skips_icon_without_one() {
+        let entry = entry("Name=Editor\n");
+        assert_eq!(expand_exec("editor %i %f", &entry).unwrap(), ["editor"]);
+    }
+}
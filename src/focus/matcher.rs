@@ -0,0 +1,226 @@
+use cosmic::desktop::fde::DesktopEntry;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{iter::Peekable, str::Chars};
+
+use super::{desktop::find_desktop_entry, FocusError, FocusOptions, Toplevel};
+
+/// How a target is compared against the toplevels reported by the compositor.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize, Serialize, clap::ValueEnum,
+)]
+pub enum MatchMode {
+    /// The app_id equals the pattern exactly.
+    Exact,
+    /// Case-insensitive equality, or one side is a dotted suffix of the other
+    /// (`firefox` matches `org.mozilla.firefox`).
+    #[default]
+    Suffix,
+    /// Case-insensitive shell glob (`*`, `?`, `[...]`) against the app_id.
+    Glob,
+    /// Regular expression against the app_id.
+    Regex,
+    /// Regular expression against the window title.
+    TitleRegex,
+    /// The app_id equals the `StartupWMClass` of the pattern's desktop entry, falling back
+    /// to suffix matching when the entry declares none.
+    WmClass,
+}
+
+/// A compiled [`MatchMode`] plus pattern.
+#[derive(Debug, Clone)]
+pub struct AppMatcher {
+    mode: MatchMode,
+    pattern: String,
+    pattern_lc: String,
+    regex: Option<Regex>,
+    wm_class: Option<String>,
+}
+
+impl AppMatcher {
+    pub fn new(mode: MatchMode, pattern: &str) -> Result<Self, FocusError> {
+        Self::with_entry(mode, pattern, None)
+    }
+
+    /// The matcher [`focus_or_launch`](super::focus_or_launch) and friends use for `app_id`:
+    /// `options.pattern` (or the app id itself) under `options.match_mode`. Without a pattern,
+    /// `options.desktop_entry` stands in for looking up the app's entry.
+    pub fn for_target(app_id: &str, options: &FocusOptions) -> Result<Self, FocusError> {
+        match options.pattern.as_deref() {
+            Some(pattern) => Self::new(options.match_mode, pattern),
+            None => Self::with_entry(options.match_mode, app_id, options.desktop_entry.as_ref()),
+        }
+    }
+
+    /// `entry`, when given, is the desktop entry of `pattern`.
+    fn with_entry(
+        mode: MatchMode,
+        pattern: &str,
+        entry: Option<&DesktopEntry>,
+    ) -> Result<Self, FocusError> {
+        let source = match mode {
+            MatchMode::Glob => Some(glob_to_regex(pattern)),
+            MatchMode::Regex | MatchMode::TitleRegex => Some(pattern.to_string()),
+            _ => None,
+        };
+        let regex = source
+            .map(|source| Regex::new(&source))
+            .transpose()
+            .map_err(|source| FocusError::InvalidPattern {
+                pattern: pattern.to_string(),
+                source,
+            })?;
+
+        let wm_class = match mode {
+            MatchMode::WmClass => {
+                let wm_class = match entry {
+                    Some(entry) => startup_wm_class(entry),
+                    None => find_desktop_entry(pattern).and_then(|entry| startup_wm_class(&entry)),
+                };
+                if wm_class.is_none() {
+                    log::info!(
+                        "No StartupWMClass known for '{}'; falling back to suffix matching",
+                        pattern
+                    );
+                }
+                wm_class
+            }
+            _ => None,
+        };
+
+        Ok(Self {
+            mode,
+            pattern: pattern.to_string(),
+            pattern_lc: pattern.to_lowercase(),
+            regex,
+            wm_class,
+        })
+    }
+
+    pub fn matches(&self, toplevel: &Toplevel) -> bool {
+        let app_id = toplevel.app_id.as_str();
+        match self.mode {
+            MatchMode::Exact => app_id == self.pattern,
+            MatchMode::Suffix => self.suffix_matches(app_id),
+            MatchMode::Glob | MatchMode::Regex => self
+                .regex
+                .as_ref()
+                .map(|regex| regex.is_match(app_id))
+                .unwrap_or(false),
+            MatchMode::TitleRegex => self
+                .regex
+                .as_ref()
+                .map(|regex| regex.is_match(&toplevel.title))
+                .unwrap_or(false),
+            MatchMode::WmClass => match self.wm_class.as_deref() {
+                Some(class) => app_id.eq_ignore_ascii_case(class),
+                None => self.suffix_matches(app_id),
+            },
+        }
+    }
+
+    fn suffix_matches(&self, app_id: &str) -> bool {
+        if app_id.is_empty() {
+            return false;
+        }
+        let candidate = app_id.to_lowercase();
+        if candidate == self.pattern_lc {
+            return true;
+        }
+        candidate.ends_with(&format!(".{}", self.pattern_lc))
+            || self.pattern_lc.ends_with(&format!(".{}", candidate))
+    }
+}
+
+fn startup_wm_class(entry: &DesktopEntry) -> Option<String> {
+    entry
+        .desktop_entry("StartupWMClass")
+        .map(|class| class.to_string())
+}
+
+/// Translates a shell glob into an anchored, case-insensitive regex.
+fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::from("(?i)^");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            '[' => match glob_class(&mut chars) {
+                Some(class) => regex.push_str(&class),
+                None => regex.push_str(r"\["),
+            },
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+/// Translates the bracket expression after a `[`, consuming it from `chars`. A `]` right
+/// after the opening bracket (or `[!`) belongs to the set. Returns `None` and leaves `chars`
+/// alone when the bracket is never closed, so it matches literally.
+fn glob_class(chars: &mut Peekable<Chars<'_>>) -> Option<String> {
+    let mut lookahead = chars.clone();
+    let mut class = String::from("[");
+    if lookahead.next_if_eq(&'!').is_some() {
+        class.push('^');
+    }
+    let mut first = true;
+    loop {
+        match lookahead.next()? {
+            ']' if !first => break,
+            // Characters the regex crate treats specially inside a class.
+            c @ ('\\' | '[' | ']' | '^' | '&' | '~') => {
+                class.push('\\');
+                class.push(c);
+            }
+            c => class.push(c),
+        }
+        first = false;
+    }
+    class.push(']');
+    *chars = lookahead;
+    Some(class)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glob_matches(glob: &str, app_id: &str) -> bool {
+        Regex::new(&glob_to_regex(glob)).unwrap().is_match(app_id)
+    }
+
+    #[test]
+    fn glob_wildcards_and_classes() {
+        assert!(glob_matches("org.gnome.*", "org.gnome.Nautilus"));
+        assert!(!glob_matches("org.gnome.*", "org.gnomex"));
+        assert!(glob_matches("fire?ox", "FireFox"));
+        assert!(glob_matches("term[0-9]", "term7"));
+        assert!(glob_matches("term[!0-9]", "termx"));
+        assert!(!glob_matches("term[!0-9]", "term7"));
+    }
+
+    #[test]
+    fn glob_leading_bracket_and_caret_are_literal_in_class() {
+        assert!(glob_matches("a[]b]", "a]"));
+        assert!(glob_matches("a[]b]", "ab"));
+        assert!(glob_matches("a[!]]", "ax"));
+        assert!(!glob_matches("a[!]]", "a]"));
+        assert!(glob_matches("a[^b]", "a^"));
+        assert!(glob_matches("a[^b]", "ab"));
+        assert!(!glob_matches("a[^b]", "ac"));
+        assert!(glob_matches(r"a[\\&~]", r"a\"));
+        assert!(glob_matches("a[&~]", "a~"));
+    }
+
+    #[test]
+    fn glob_unterminated_bracket_is_literal() {
+        assert!(glob_matches("app[", "app["));
+        assert!(glob_matches("app[x*", "app[xyz"));
+        assert!(!glob_matches("app[x*", "appx"));
+        assert!(glob_matches("[", "["));
+        assert!(glob_matches("[!", "[!"));
+    }
+}
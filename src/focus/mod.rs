@@ -0,0 +1,565 @@
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+use cosmic::desktop::fde::DesktopEntry;
+use serde::{Deserialize, Serialize};
+use wayland_client::{Connection, EventQueue, Proxy};
+
+mod daemon;
+mod dbus;
+mod desktop;
+mod error;
+mod event;
+mod launch;
+mod matcher;
+mod policy;
+mod service;
+mod systemd;
+mod tracker;
+mod watch;
+
+pub use daemon::{forward_list, forward_to_daemon, run_daemon, socket_path};
+pub use desktop::find_desktop_entry;
+pub use error::{FocusError, FocusErrorKind, FocusOutcome};
+pub use event::{RunningApps, ToplevelEvent};
+pub use matcher::{AppMatcher, MatchMode};
+pub use policy::FocusPolicy;
+use tracker::{CosmicToplevelHandle, TrackedToplevel};
+pub use tracker::{Toplevel, ToplevelOrder, ToplevelTracker, DEFAULT_DISCOVERY_TIMEOUT};
+pub use watch::{toplevel_stream, watch_toplevels, ToplevelStream, WatchHandle};
+
+/// Windows of the target app that can be managed, in announcement order.
+fn matching_toplevels<'a>(
+    tracker: &'a ToplevelTracker,
+    matcher: &AppMatcher,
+) -> Vec<&'a TrackedToplevel> {
+    let mut matches: Vec<&TrackedToplevel> = tracker
+        .tracked()
+        .iter()
+        .filter(|tracked| tracked.cosmic.is_some())
+        .filter(|tracked| matcher.matches(&tracked.info))
+        .collect();
+    matches.sort_by_key(|tracked| tracked.info.id);
+    matches
+}
+
+/// The newest fully described window matching `matcher` that is not one of `known`.
+fn new_window(
+    tracker: &ToplevelTracker,
+    matcher: &AppMatcher,
+    known: &[u64],
+) -> Option<CosmicToplevelHandle> {
+    matching_toplevels(tracker, matcher)
+        .into_iter()
+        .rev()
+        .find(|tracked| tracked.is_described() && !known.contains(&tracked.info.id))
+        .and_then(|tracked| tracked.cosmic.clone())
+}
+
+/// Dispatches events until [`new_window`] finds the launched window, returning its handle,
+/// or `None` once `timeout` expires.
+fn wait_for_window(
+    tracker: &mut ToplevelTracker,
+    event_queue: &mut EventQueue<ToplevelTracker>,
+    matcher: &AppMatcher,
+    known: &[u64],
+    timeout: Duration,
+) -> Result<Option<CosmicToplevelHandle>, FocusError> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(handle) = new_window(tracker, matcher, known) {
+            return Ok(Some(handle));
+        }
+
+        let now = Instant::now();
+        if now >= deadline {
+            return Ok(None);
+        }
+        tracker.dispatch_timeout(event_queue, deadline - now)?;
+    }
+}
+
+pub fn init_logger(verbosity: u8) {
+    let level = match verbosity {
+        0 => "warn",
+        1 => "info",
+        _ => "debug",
+    };
+    let mut builder =
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(level));
+    builder.format_timestamp_millis();
+    let _ = builder.try_init();
+}
+
+/// Tunables for [`focus_or_launch`].
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FocusOptions {
+    /// Shell command to run when no window matches, instead of the app's desktop entry.
+    pub launch_cmd: Option<String>,
+    /// The app's desktop entry, for callers that have already resolved it. Saves launching
+    /// and [`MatchMode::WmClass`] from reading every desktop file; never sent to the daemon.
+    #[serde(skip)]
+    pub desktop_entry: Option<DesktopEntry>,
+    /// Minimize the app instead of re-activating it when it already has focus.
+    pub minimize_on_repeat: bool,
+    /// How running windows are matched against the target.
+    pub match_mode: MatchMode,
+    /// Pattern handed to the matcher instead of the app id.
+    pub pattern: Option<String>,
+    /// Which window wins when the app has several.
+    pub policy: FocusPolicy,
+    /// Output (by name) the policy treats as current instead of the focused one.
+    pub output: Option<String>,
+    /// Upper bound for toplevel discovery (default: [`DEFAULT_DISCOVERY_TIMEOUT`]).
+    pub discovery_timeout: Option<Duration>,
+    /// xdg-activation token passed to a launched app so it may take focus.
+    pub activation_token: Option<String>,
+    /// After launching, wait this long for the app's window and activate it.
+    pub wait: Option<Duration>,
+    /// Close only the app's focused window instead of all of them.
+    pub focused_only: bool,
+    /// After closing, terminate the app's systemd scope if its windows are still open this
+    /// long afterwards. Ignored with `focused_only`, since the scope holds the app's other
+    /// windows too.
+    pub kill_after: Option<Duration>,
+    /// Move the chosen window to the current workspace and output before activating it,
+    /// instead of switching to where it is.
+    pub summon: bool,
+}
+
+/// What [`focus_with`] did, before any waiting for a launched window.
+enum Acted {
+    Focused,
+    Minimized,
+    /// No window matched, so the app was launched; the next matching window is its.
+    Launched,
+}
+
+/// Focuses, minimizes or launches the app against an already discovered tracker.
+fn focus_with(
+    conn: &Connection,
+    tracker: &ToplevelTracker,
+    matcher: &AppMatcher,
+    app_id: &str,
+    options: &FocusOptions,
+) -> Result<Acted, FocusError> {
+    let mgr = tracker
+        .manager()
+        .ok_or(FocusError::MissingProtocol("zcosmic_toplevel_manager_v1"))?;
+    let matches = matching_toplevels(tracker, matcher);
+
+    if options.minimize_on_repeat && matches.iter().any(|tracked| tracked.info.activated) {
+        for handle in matches.iter().filter_map(|tracked| tracked.cosmic.as_ref()) {
+            mgr.set_minimized(handle);
+        }
+        log::info!(
+            "'{}' already focused; minimized {} window(s)",
+            app_id,
+            matches.len()
+        );
+        conn.flush()
+            .map_err(FocusError::wayland("flush minimize request"))?;
+        return Ok(Acted::Minimized);
+    }
+
+    let target = options
+        .policy
+        .select(tracker, &matches, options.output.as_deref());
+    if let Some(handle) = target {
+        let seat = tracker
+            .seat()
+            .ok_or(FocusError::MissingProtocol("wl_seat"))?;
+        if options.summon {
+            summon(tracker, mgr, handle, options.output.as_deref());
+        }
+        mgr.activate(handle, seat);
+        log::info!(
+            "Requested activation for '{}' (handle {})",
+            app_id,
+            handle.id()
+        );
+        conn.flush()
+            .map_err(FocusError::wayland("flush activation request"))?;
+        return Ok(Acted::Focused);
+    }
+
+    log::info!("No running instance matched; launching '{}'", app_id);
+    launch::launch(app_id, options).map_err(|source| FocusError::LaunchFailed {
+        app_id: app_id.to_string(),
+        source,
+    })?;
+    Ok(Acted::Launched)
+}
+
+/// Moves `handle` to the active workspace of the current output unless it is already there.
+fn summon(
+    tracker: &ToplevelTracker,
+    mgr: &tracker::CosmicToplevelManager,
+    handle: &CosmicToplevelHandle,
+    output: Option<&str>,
+) {
+    if mgr.version() < 4 {
+        log::warn!(
+            "cosmic_toplevel_manager v{} cannot move windows to ext workspaces; focusing in place",
+            mgr.version()
+        );
+        return;
+    }
+    let Some((workspace, output)) = policy::summon_destination(tracker, output) else {
+        log::warn!("No active workspace found to summon the window to");
+        return;
+    };
+    let already_there = tracker
+        .tracked()
+        .iter()
+        .find(|tracked| tracked.cosmic.as_ref() == Some(handle))
+        .is_some_and(|tracked| tracked.workspaces.contains(workspace));
+    if !already_there {
+        log::info!(
+            "Summoning handle {} to workspace {}",
+            handle.id(),
+            workspace.id()
+        );
+        mgr.move_to_ext_workspace(handle, workspace, output);
+    }
+}
+
+/// Activates the window a launched app just opened.
+fn activate_launched(
+    conn: &Connection,
+    tracker: &ToplevelTracker,
+    app_id: &str,
+    handle: &CosmicToplevelHandle,
+) -> Result<(), FocusError> {
+    let mgr = tracker
+        .manager()
+        .ok_or(FocusError::MissingProtocol("zcosmic_toplevel_manager_v1"))?;
+    let seat = tracker
+        .seat()
+        .ok_or(FocusError::MissingProtocol("wl_seat"))?;
+    mgr.activate(handle, seat);
+    log::info!(
+        "Activated new window of '{}' (handle {})",
+        app_id,
+        handle.id()
+    );
+    conn.flush()
+        .map_err(FocusError::wayland("flush activation request"))
+}
+
+pub fn focus_or_launch(app_id: &str, options: &FocusOptions) -> Result<FocusOutcome, FocusError> {
+    log::debug!("Starting focus helper for {}", app_id);
+
+    let (matcher, conn, mut event_queue, mut tracker) = connect_matching(app_id, options)?;
+    match focus_with(&conn, &tracker, &matcher, app_id, options)? {
+        Acted::Focused => return Ok(FocusOutcome::Focused),
+        Acted::Minimized => return Ok(FocusOutcome::Minimized),
+        Acted::Launched => {}
+    }
+
+    if let Some(timeout) = options.wait {
+        await_launched(
+            &conn,
+            &mut event_queue,
+            &mut tracker,
+            &matcher,
+            app_id,
+            &[],
+            timeout,
+        )?;
+    }
+    Ok(FocusOutcome::Launched)
+}
+
+/// Waits for the window of a just launched app and activates it.
+fn await_launched(
+    conn: &Connection,
+    event_queue: &mut EventQueue<ToplevelTracker>,
+    tracker: &mut ToplevelTracker,
+    matcher: &AppMatcher,
+    app_id: &str,
+    known: &[u64],
+    timeout: Duration,
+) -> Result<(), FocusError> {
+    log::debug!("Waiting up to {:?} for a window of '{}'", timeout, app_id);
+    let handle =
+        wait_for_window(tracker, event_queue, matcher, known, timeout)?.ok_or_else(|| {
+            FocusError::NeverAppeared {
+                app_id: app_id.to_string(),
+                timeout,
+            }
+        })?;
+    activate_launched(conn, tracker, app_id, &handle)
+}
+
+/// Starts a new instance of the app even if it already has windows. With
+/// [`FocusOptions::wait`], the new instance's first window is activated once it appears.
+pub fn launch_app(app_id: &str, options: &FocusOptions) -> Result<(), FocusError> {
+    let launch = || {
+        launch::launch(app_id, options).map_err(|source| FocusError::LaunchFailed {
+            app_id: app_id.to_string(),
+            source,
+        })
+    };
+    let Some(timeout) = options.wait else {
+        return launch();
+    };
+
+    let (matcher, conn, mut event_queue, mut tracker) = connect_matching(app_id, options)?;
+    let known: Vec<u64> = matching_toplevels(&tracker, &matcher)
+        .iter()
+        .map(|tracked| tracked.info.id)
+        .collect();
+    launch()?;
+    await_launched(
+        &conn,
+        &mut event_queue,
+        &mut tracker,
+        &matcher,
+        app_id,
+        &known,
+        timeout,
+    )
+}
+
+/// Asks every window matching `matcher` (or just the focused one with
+/// [`FocusOptions::focused_only`]) to close, returning the ids of the windows asked.
+fn close_with(
+    conn: &Connection,
+    tracker: &ToplevelTracker,
+    matcher: &AppMatcher,
+    options: &FocusOptions,
+) -> Result<Vec<u64>, FocusError> {
+    let mgr = tracker
+        .manager()
+        .ok_or(FocusError::MissingProtocol("zcosmic_toplevel_manager_v1"))?;
+    let mut closed = Vec::new();
+    for tracked in matching_toplevels(tracker, matcher)
+        .into_iter()
+        .filter(|tracked| !options.focused_only || tracked.info.activated)
+    {
+        if let Some(handle) = tracked.cosmic.as_ref() {
+            mgr.close(handle);
+            closed.push(tracked.info.id);
+        }
+    }
+    conn.flush()
+        .map_err(FocusError::wayland("flush close request"))?;
+    Ok(closed)
+}
+
+/// Whether any of the windows in `ids` is still open.
+fn any_open(tracker: &ToplevelTracker, ids: &[u64]) -> bool {
+    tracker
+        .tracked()
+        .iter()
+        .any(|tracked| ids.contains(&tracked.info.id))
+}
+
+/// How long to wait before terminating the windows `close_with` asked to close, unless
+/// `options` only closed the focused window.
+fn kill_grace(options: &FocusOptions, closed: &[u64]) -> Option<Duration> {
+    if options.focused_only && options.kill_after.is_some() {
+        log::warn!("Not terminating the app's scope: it also holds the windows left open");
+        return None;
+    }
+    options.kill_after.filter(|_| !closed.is_empty())
+}
+
+/// Sends SIGTERM to the systemd scopes of `app_id` when any of its windows in `closed` is
+/// still open. The scopes are looked up by `app_id` and by those windows' app ids.
+fn kill_remaining(tracker: &ToplevelTracker, app_id: &str, closed: &[u64]) {
+    let mut app_ids: Vec<&str> = tracker
+        .tracked()
+        .iter()
+        .filter(|tracked| closed.contains(&tracked.info.id))
+        .map(|tracked| tracked.info.app_id.as_str())
+        .collect();
+    if app_ids.is_empty() {
+        return;
+    }
+    app_ids.push(app_id);
+    app_ids.sort_unstable();
+    app_ids.dedup();
+
+    match systemd::kill_app_scopes(&app_ids) {
+        Ok(0) => log::warn!("No app scope found for {:?}; cannot terminate it", app_ids),
+        Ok(killed) => log::info!("Sent SIGTERM to {} scope(s) of {:?}", killed, app_ids),
+        Err(err) => log::warn!("Failed to terminate {:?}: {:#}", app_ids, err),
+    }
+}
+
+/// Minimizes every window matching `matcher`, returning how many there were.
+fn minimize_with(
+    conn: &Connection,
+    tracker: &ToplevelTracker,
+    matcher: &AppMatcher,
+) -> Result<usize, FocusError> {
+    let mgr = tracker
+        .manager()
+        .ok_or(FocusError::MissingProtocol("zcosmic_toplevel_manager_v1"))?;
+    let matches = matching_toplevels(tracker, matcher);
+    for handle in matches.iter().filter_map(|tracked| tracked.cosmic.as_ref()) {
+        mgr.set_minimized(handle);
+    }
+    conn.flush()
+        .map_err(FocusError::wayland("flush minimize request"))?;
+    Ok(matches.len())
+}
+
+/// Activates the most recently focused window of the app that had focus before the current
+/// one, returning that app's id.
+fn previous_with(conn: &Connection, tracker: &ToplevelTracker) -> Result<String, FocusError> {
+    let mgr = tracker
+        .manager()
+        .ok_or(FocusError::MissingProtocol("zcosmic_toplevel_manager_v1"))?;
+    let seat = tracker
+        .seat()
+        .ok_or(FocusError::MissingProtocol("wl_seat"))?;
+    let current = tracker
+        .tracked()
+        .iter()
+        .find(|tracked| tracked.info.activated)
+        .map(|tracked| tracked.info.app_id.as_str());
+
+    let (previous, handle) = tracker
+        .ordered(ToplevelOrder::Recent)
+        .into_iter()
+        .filter(|tracked| tracked.last_activated > 0)
+        .filter(|tracked| {
+            current.is_none_or(|current| !tracked.info.app_id.eq_ignore_ascii_case(current))
+        })
+        .find_map(|tracked| Some((&tracked.info, tracked.cosmic.as_ref()?)))
+        .ok_or(FocusError::NoHistory)?;
+    mgr.activate(handle, seat);
+    log::info!(
+        "Switching back to '{}' (handle {})",
+        previous.app_id,
+        handle.id()
+    );
+    conn.flush()
+        .map_err(FocusError::wayland("flush activation request"))?;
+    Ok(previous.app_id.clone())
+}
+
+/// Switches back to the app that was focused before the current one.
+///
+/// Only the focus daemon sees focus change over time, so this fails with
+/// [`FocusError::Daemon`] when it is not running, and with [`FocusError::NoHistory`] when it
+/// has not seen another app focused yet.
+pub fn focus_previous() -> Result<(), FocusError> {
+    match daemon::forward_previous() {
+        Some(result) => result.map(drop),
+        None => Err(FocusError::Daemon(io::Error::new(
+            io::ErrorKind::NotFound,
+            "no focus daemon is running to remember which app was focused before",
+        ))),
+    }
+}
+
+/// Connects to the compositor and discovers its windows, returning the target's matcher
+/// alongside.
+fn connect_matching(
+    app_id: &str,
+    options: &FocusOptions,
+) -> Result<
+    (
+        AppMatcher,
+        Connection,
+        EventQueue<ToplevelTracker>,
+        ToplevelTracker,
+    ),
+    FocusError,
+> {
+    let matcher = AppMatcher::for_target(app_id, options)?;
+    let (conn, mut event_queue, mut tracker) = ToplevelTracker::connect()?;
+    tracker.discover(
+        &mut event_queue,
+        options
+            .discovery_timeout
+            .unwrap_or(DEFAULT_DISCOVERY_TIMEOUT),
+    )?;
+    Ok((matcher, conn, event_queue, tracker))
+}
+
+/// Connects, discovers and runs `action` against the windows matching the target.
+fn with_matches<T>(
+    app_id: &str,
+    options: &FocusOptions,
+    action: impl FnOnce(&Connection, &ToplevelTracker, &AppMatcher) -> Result<T, FocusError>,
+) -> Result<T, FocusError> {
+    let (matcher, conn, _event_queue, tracker) = connect_matching(app_id, options)?;
+    action(&conn, &tracker, &matcher)
+}
+
+/// Asks every window of the app, or only its focused one, to close. Returns how many windows
+/// were asked.
+///
+/// With [`FocusOptions::kill_after`], windows still open once the grace period is over get
+/// their app's systemd scope terminated.
+pub fn close_app(app_id: &str, options: &FocusOptions) -> Result<usize, FocusError> {
+    let (matcher, conn, mut event_queue, mut tracker) = connect_matching(app_id, options)?;
+    let closed = close_with(&conn, &tracker, &matcher, options)?;
+    log::info!("Asked {} window(s) of '{}' to close", closed.len(), app_id);
+
+    if let Some(grace) = kill_grace(options, &closed) {
+        let deadline = Instant::now() + grace;
+        loop {
+            if !any_open(&tracker, &closed) {
+                return Ok(closed.len());
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            tracker.dispatch_timeout(&mut event_queue, deadline - now)?;
+        }
+        kill_remaining(&tracker, app_id, &closed);
+    }
+    Ok(closed.len())
+}
+
+/// Minimizes every window of the app. Returns how many windows matched.
+pub fn minimize_app(app_id: &str, options: &FocusOptions) -> Result<usize, FocusError> {
+    let minimized = with_matches(app_id, options, minimize_with)?;
+    log::info!("Minimized {} window(s) of '{}'", minimized, app_id);
+    Ok(minimized)
+}
+
+/// The app's windows, in announcement order.
+pub fn app_toplevels(app_id: &str, options: &FocusOptions) -> Result<Vec<Toplevel>, FocusError> {
+    with_matches(app_id, options, |_, tracker, matcher| {
+        Ok(matching_toplevels(tracker, matcher)
+            .into_iter()
+            .map(|tracked| tracker.snapshot(tracked))
+            .collect())
+    })
+}
+
+/// Running app ids, most recently focused first; `discovery_timeout` bounds how long the
+/// compositor gets to describe its windows.
+pub fn list_running_apps(discovery_timeout: Duration) -> Result<Vec<String>, FocusError> {
+    let (_conn, mut event_queue, mut tracker) = ToplevelTracker::connect()?;
+    tracker.discover(&mut event_queue, discovery_timeout)?;
+
+    let mut apps = tracker.running_apps();
+    apps.sort();
+    Ok(apps)
+}
+
+/// Lists every toplevel window with its title, states, outputs and workspaces.
+///
+/// A fresh connection only learns which window is focused right now, so
+/// [`ToplevelOrder::Recent`] puts that window first and the rest in announcement order, newest
+/// first. [`forward_list`] asks the focus daemon instead, which remembers the focus order.
+pub fn list_toplevels(
+    order: ToplevelOrder,
+    discovery_timeout: Duration,
+) -> Result<Vec<Toplevel>, FocusError> {
+    let (_conn, mut event_queue, mut tracker) = ToplevelTracker::connect()?;
+    tracker.discover(&mut event_queue, discovery_timeout)?;
+    Ok(tracker.toplevels_by(order))
+}
@@ -0,0 +1,250 @@
+use serde::{Deserialize, Serialize};
+use wayland_client::protocol::wl_output;
+
+use super::tracker::{CosmicToplevelHandle, ToplevelTracker, TrackedToplevel, WorkspaceHandle};
+
+/// Which of an app's windows gets activated when it has several.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize, Serialize, clap::ValueEnum,
+)]
+pub enum FocusPolicy {
+    /// Windows on the current workspace first, then on the current output, then the rest.
+    #[default]
+    CurrentWorkspace,
+    /// Windows on the current output first, then the rest.
+    CurrentOutput,
+    /// Ignore window placement.
+    Any,
+}
+
+/// Where the user currently is: derived from the activated toplevel, or from an explicitly
+/// requested output.
+struct Placement<'a> {
+    workspaces: Vec<&'a WorkspaceHandle>,
+    outputs: Vec<&'a wl_output::WlOutput>,
+}
+
+impl<'a> Placement<'a> {
+    fn current(tracker: &'a ToplevelTracker, output: Option<&str>) -> Self {
+        let focused = tracker
+            .tracked()
+            .iter()
+            .find(|tracked| tracked.info.activated);
+
+        let outputs: Vec<&wl_output::WlOutput> = match output {
+            Some(name) => tracker.output_by_name(name).into_iter().collect(),
+            None => focused
+                .map(|tracked| tracked.outputs.iter().collect())
+                .unwrap_or_default(),
+        };
+
+        let workspaces: Vec<&WorkspaceHandle> = match (output, focused) {
+            // Only the requested output's workspace counts, not those shown on other outputs.
+            (Some(_), _) => outputs
+                .iter()
+                .filter_map(|output| tracker.active_workspace_on(output))
+                .collect(),
+            (None, Some(tracked)) => {
+                let shown: Vec<&WorkspaceHandle> = tracked
+                    .workspaces
+                    .iter()
+                    .filter(|handle| tracker.is_workspace_active(handle))
+                    .collect();
+                if shown.is_empty() {
+                    tracker.active_workspaces().collect()
+                } else {
+                    shown
+                }
+            }
+            (None, None) => tracker.active_workspaces().collect(),
+        };
+
+        Self {
+            workspaces,
+            outputs,
+        }
+    }
+
+    fn on_workspace(&self, tracked: &TrackedToplevel) -> bool {
+        tracked
+            .workspaces
+            .iter()
+            .any(|handle| self.workspaces.contains(&handle))
+    }
+
+    fn on_output(&self, tracked: &TrackedToplevel) -> bool {
+        tracked
+            .outputs
+            .iter()
+            .any(|output| self.outputs.contains(&output))
+    }
+}
+
+/// Where a summoned window goes: the active workspace of the current output, along with that
+/// output. Without a focused window or requested output, the first output showing a workspace
+/// is used.
+pub(crate) fn summon_destination<'a>(
+    tracker: &'a ToplevelTracker,
+    output: Option<&str>,
+) -> Option<(&'a WorkspaceHandle, &'a wl_output::WlOutput)> {
+    let placement = Placement::current(tracker, output);
+    let candidates: Vec<&wl_output::WlOutput> = if placement.outputs.is_empty() {
+        tracker.outputs().collect()
+    } else {
+        placement.outputs
+    };
+    candidates.into_iter().find_map(|output| {
+        tracker
+            .active_workspace_on(output)
+            .map(|workspace| (workspace, output))
+    })
+}
+
+impl FocusPolicy {
+    fn rank(self, placement: &Placement, tracked: &TrackedToplevel) -> u8 {
+        match self {
+            FocusPolicy::CurrentWorkspace if placement.on_workspace(tracked) => 2,
+            FocusPolicy::CurrentWorkspace | FocusPolicy::CurrentOutput
+                if placement.on_output(tracked) =>
+            {
+                1
+            }
+            _ => 0,
+        }
+    }
+
+    /// Picks the window to activate among `matches` (in announcement order); see [`pick`].
+    pub(crate) fn select<'a>(
+        self,
+        tracker: &ToplevelTracker,
+        matches: &[&'a TrackedToplevel],
+        output: Option<&str>,
+    ) -> Option<&'a CosmicToplevelHandle> {
+        let placement = Placement::current(tracker, output);
+        let candidates: Vec<Candidate> = matches
+            .iter()
+            .map(|tracked| Candidate {
+                id: tracked.info.id,
+                rank: self.rank(&placement, tracked),
+                activated: tracked.info.activated,
+                last_activated: tracked.last_activated,
+            })
+            .collect();
+        matches[pick(&candidates)?].cosmic.as_ref()
+    }
+}
+
+/// What [`pick`] weighs about a matching window.
+#[derive(Debug, Clone, Copy)]
+struct Candidate {
+    id: u64,
+    /// Preference tier from [`FocusPolicy`]; higher is better.
+    rank: u8,
+    activated: bool,
+    /// When the window last gained focus; 0 if never.
+    last_activated: u64,
+}
+
+/// The index of the window to activate among `candidates` (in announcement order).
+///
+/// When one of them already has focus, the next window of the same preference tier is chosen
+/// so repeated requests cycle without leaving the current workspace or output. A focused
+/// window alone in its tier (e.g. one window per workspace) cycles through every candidate
+/// instead. Otherwise the best-ranked window wins, ties going to the most recently focused one
+/// and then to the most recently announced one.
+fn pick(candidates: &[Candidate]) -> Option<usize> {
+    let Some(active) = candidates.iter().find(|candidate| candidate.activated) else {
+        return (0..candidates.len()).max_by_key(|&idx| {
+            let candidate = &candidates[idx];
+            (candidate.rank, candidate.last_activated, candidate.id)
+        });
+    };
+
+    let mut cycle: Vec<usize> = (0..candidates.len())
+        .filter(|&idx| candidates[idx].rank == active.rank)
+        .collect();
+    if cycle.len() < 2 {
+        cycle = (0..candidates.len()).collect();
+    }
+    let pos = cycle
+        .iter()
+        .position(|&idx| candidates[idx].id == active.id)
+        .unwrap_or(0);
+    let next = (pos + 1) % cycle.len();
+    log::debug!(
+        "Target app already focused; cycling to window {} of {}",
+        next + 1,
+        cycle.len()
+    );
+    Some(cycle[next])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(id: u64, rank: u8, last_activated: u64) -> Candidate {
+        Candidate {
+            id,
+            rank,
+            activated: false,
+            last_activated,
+        }
+    }
+
+    fn focused(candidate: Candidate) -> Candidate {
+        Candidate {
+            activated: true,
+            ..candidate
+        }
+    }
+
+    #[test]
+    fn pick_prefers_rank_then_recent_focus_then_newest() {
+        assert_eq!(pick(&[]), None);
+        assert_eq!(
+            pick(&[window(0, 0, 9), window(1, 2, 0), window(2, 1, 0)]),
+            Some(1)
+        );
+        assert_eq!(
+            pick(&[window(0, 1, 5), window(1, 1, 3), window(2, 1, 0)]),
+            Some(0)
+        );
+        assert_eq!(
+            pick(&[window(0, 1, 0), window(1, 1, 0), window(2, 0, 0)]),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn pick_cycles_within_the_focused_tier() {
+        let candidates = [
+            window(0, 2, 0),
+            window(1, 1, 0),
+            focused(window(2, 2, 7)),
+            window(3, 2, 0),
+        ];
+        assert_eq!(pick(&candidates), Some(3));
+
+        let candidates = [
+            window(0, 2, 0),
+            window(1, 1, 0),
+            window(2, 2, 0),
+            focused(window(3, 2, 7)),
+        ];
+        assert_eq!(pick(&candidates), Some(0));
+    }
+
+    #[test]
+    fn pick_cycles_through_all_when_alone_in_tier() {
+        let candidates = [window(0, 1, 3), focused(window(1, 2, 7)), window(2, 0, 0)];
+        assert_eq!(pick(&candidates), Some(2));
+        let candidates = [window(0, 1, 3), window(1, 0, 0), focused(window(2, 2, 7))];
+        assert_eq!(pick(&candidates), Some(0));
+    }
+
+    #[test]
+    fn pick_keeps_a_lone_focused_window() {
+        assert_eq!(pick(&[focused(window(4, 2, 1))]), Some(0));
+    }
+}
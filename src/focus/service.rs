@@ -0,0 +1,231 @@
+use clap::ValueEnum;
+use futures::channel::oneshot;
+use smithay_client_toolkit::reexports::calloop::channel::Sender;
+use std::{collections::HashMap, fmt::Display, time::Duration};
+use zbus::{interface, object_server::SignalEmitter, zvariant::OwnedValue};
+
+use super::{
+    FocusError, FocusErrorKind, FocusOptions, FocusOutcome, FocusPolicy, MatchMode, Toplevel,
+    ToplevelOrder,
+};
+
+pub(crate) const BUS_NAME: &str = "com.system76.CosmicAppFocus1";
+pub(crate) const OBJECT_PATH: &str = "/com/system76/CosmicAppFocus1";
+
+/// A D-Bus call forwarded to the daemon's event loop, which owns the tracker.
+pub(crate) enum ServiceRequest {
+    FocusOrLaunch {
+        app_id: String,
+        options: FocusOptions,
+        reply: oneshot::Sender<Result<FocusOutcome, FocusError>>,
+    },
+    FocusPrevious {
+        reply: oneshot::Sender<Result<String, FocusError>>,
+    },
+    ListToplevels {
+        order: ToplevelOrder,
+        reply: oneshot::Sender<Vec<Toplevel>>,
+    },
+    Close {
+        app_id: String,
+        options: FocusOptions,
+        reply: oneshot::Sender<Result<usize, FocusError>>,
+    },
+    Minimize {
+        app_id: String,
+        options: FocusOptions,
+        reply: oneshot::Sender<Result<usize, FocusError>>,
+    },
+}
+
+/// Errors returned over D-Bus, named after [`FocusErrorKind`].
+#[derive(Debug, zbus::DBusError)]
+#[zbus(prefix = "com.system76.CosmicAppFocus1.Error")]
+pub(crate) enum ServiceError {
+    #[zbus(error)]
+    ZBus(zbus::Error),
+    NoCompositor(String),
+    MissingProtocol(String),
+    InvalidPattern(String),
+    LaunchFailed(String),
+    NeverAppeared(String),
+    Wayland(String),
+    Daemon(String),
+    NoHistory(String),
+    InvalidOption(String),
+}
+
+impl From<FocusError> for ServiceError {
+    fn from(err: FocusError) -> Self {
+        let message = err.to_string();
+        match err.kind() {
+            FocusErrorKind::NoCompositor => ServiceError::NoCompositor(message),
+            FocusErrorKind::MissingProtocol => ServiceError::MissingProtocol(message),
+            FocusErrorKind::InvalidPattern => ServiceError::InvalidPattern(message),
+            FocusErrorKind::LaunchFailed => ServiceError::LaunchFailed(message),
+            FocusErrorKind::NeverAppeared => ServiceError::NeverAppeared(message),
+            FocusErrorKind::Wayland => ServiceError::Wayland(message),
+            FocusErrorKind::Daemon => ServiceError::Daemon(message),
+            FocusErrorKind::NoHistory => ServiceError::NoHistory(message),
+        }
+    }
+}
+
+/// The `com.system76.CosmicAppFocus1` interface served by the focus daemon.
+pub(crate) struct FocusService {
+    pub(crate) requests: Sender<ServiceRequest>,
+}
+
+impl FocusService {
+    async fn call<T>(
+        &self,
+        request: impl FnOnce(oneshot::Sender<T>) -> ServiceRequest,
+    ) -> Result<T, ServiceError> {
+        let (reply, answer) = oneshot::channel();
+        let daemon_gone = || ServiceError::Daemon("focus daemon is shutting down".to_string());
+        self.requests
+            .send(request(reply))
+            .map_err(|_| daemon_gone())?;
+        answer.await.map_err(|_| daemon_gone())
+    }
+}
+
+#[interface(name = "com.system76.CosmicAppFocus1")]
+impl FocusService {
+    /// Focuses the app, or launches it when no window matches. Returns `focused`,
+    /// `launched` or `minimized`.
+    ///
+    /// `options` accepts the CLI flags by name: `launch_cmd`, `minimize_on_repeat`, `match`,
+    /// `pattern`, `policy`, `output`, `summon`, `activation_token`, `focused_only` (strings or
+    /// booleans), `wait_ms` and `kill_after_ms` (u32).
+    async fn focus_or_launch(
+        &self,
+        app_id: String,
+        options: HashMap<String, OwnedValue>,
+    ) -> Result<String, ServiceError> {
+        let options = parse_options(options)?;
+        let outcome = self
+            .call(|reply| ServiceRequest::FocusOrLaunch {
+                app_id,
+                options,
+                reply,
+            })
+            .await??;
+        Ok(match outcome {
+            FocusOutcome::Focused => "focused",
+            FocusOutcome::Launched => "launched",
+            FocusOutcome::Minimized => "minimized",
+        }
+        .to_string())
+    }
+
+    /// Switches back to the app that was focused before the current one. Returns its app id.
+    async fn focus_previous(&self) -> Result<String, ServiceError> {
+        Ok(self
+            .call(|reply| ServiceRequest::FocusPrevious { reply })
+            .await??)
+    }
+
+    /// Every toplevel window with its title, states, outputs and workspaces, in `order`:
+    /// `opened` (oldest first) or `recent` (most recently focused first).
+    async fn list_toplevels(&self, order: String) -> Result<Vec<Toplevel>, ServiceError> {
+        let order = ToplevelOrder::from_str(&order, true)
+            .map_err(|err| ServiceError::InvalidOption(format!("order: {err}")))?;
+        self.call(|reply| ServiceRequest::ListToplevels { order, reply })
+            .await
+    }
+
+    /// Asks every window of the app (only the focused one with `focused_only`) to close.
+    /// Returns how many windows were asked. With `kill_after_ms`, which excludes
+    /// `focused_only`, the app's systemd scope is sent SIGTERM if any of them is still open
+    /// after that long.
+    async fn close(
+        &self,
+        app_id: String,
+        options: HashMap<String, OwnedValue>,
+    ) -> Result<u32, ServiceError> {
+        let options = parse_options(options)?;
+        let closed = self
+            .call(|reply| ServiceRequest::Close {
+                app_id,
+                options,
+                reply,
+            })
+            .await??;
+        Ok(closed as u32)
+    }
+
+    /// Minimizes every window of the app. Returns how many windows matched.
+    async fn minimize(
+        &self,
+        app_id: String,
+        options: HashMap<String, OwnedValue>,
+    ) -> Result<u32, ServiceError> {
+        let options = parse_options(options)?;
+        let minimized = self
+            .call(|reply| ServiceRequest::Minimize {
+                app_id,
+                options,
+                reply,
+            })
+            .await??;
+        Ok(minimized as u32)
+    }
+
+    /// Emitted with the full window list whenever a window opens, closes or changes.
+    #[zbus(signal)]
+    pub(crate) async fn toplevels_changed(
+        emitter: &SignalEmitter<'_>,
+        toplevels: Vec<Toplevel>,
+    ) -> zbus::Result<()>;
+}
+
+fn parse_options(dict: HashMap<String, OwnedValue>) -> Result<FocusOptions, ServiceError> {
+    let mut options = FocusOptions::default();
+    for (key, value) in dict {
+        match key.as_str() {
+            "launch_cmd" => options.launch_cmd = Some(option(&key, value)?),
+            "minimize_on_repeat" => options.minimize_on_repeat = option(&key, value)?,
+            "match" => options.match_mode = enum_option::<MatchMode>(&key, value)?,
+            "pattern" => options.pattern = Some(option(&key, value)?),
+            "policy" => options.policy = enum_option::<FocusPolicy>(&key, value)?,
+            "output" => options.output = Some(option(&key, value)?),
+            "summon" => options.summon = option(&key, value)?,
+            "activation_token" => options.activation_token = Some(option(&key, value)?),
+            "wait_ms" => {
+                let millis: u32 = option(&key, value)?;
+                options.wait = Some(Duration::from_millis(millis.into()));
+            }
+            "focused_only" => options.focused_only = option(&key, value)?,
+            "kill_after_ms" => {
+                let millis: u32 = option(&key, value)?;
+                options.kill_after = Some(Duration::from_millis(millis.into()));
+            }
+            _ => {
+                return Err(ServiceError::InvalidOption(format!(
+                    "unknown option '{key}'"
+                )))
+            }
+        }
+    }
+    if options.focused_only && options.kill_after.is_some() {
+        // The scope also holds the windows `focused_only` leaves open.
+        return Err(ServiceError::InvalidOption(
+            "kill_after_ms cannot be combined with focused_only".to_string(),
+        ));
+    }
+    Ok(options)
+}
+
+fn option<T>(key: &str, value: OwnedValue) -> Result<T, ServiceError>
+where
+    T: TryFrom<OwnedValue>,
+    T::Error: Display,
+{
+    T::try_from(value).map_err(|err| ServiceError::InvalidOption(format!("{key}: {err}")))
+}
+
+fn enum_option<T: ValueEnum>(key: &str, value: OwnedValue) -> Result<T, ServiceError> {
+    let name: String = option(key, value)?;
+    T::from_str(&name, true).map_err(|err| ServiceError::InvalidOption(format!("{key}: {err}")))
+}
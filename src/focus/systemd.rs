@@ -0,0 +1,149 @@
+use anyhow::{Context, Result};
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+};
+use zbus::{
+    blocking::Connection,
+    zvariant::{OwnedObjectPath, Value},
+};
+
+#[zbus::proxy(
+    interface = "org.freedesktop.systemd1.Manager",
+    default_service = "org.freedesktop.systemd1",
+    default_path = "/org/freedesktop/systemd1"
+)]
+trait SystemdManager {
+    fn start_transient_unit(
+        &self,
+        name: &str,
+        mode: &str,
+        properties: &[(&str, Value<'_>)],
+        aux: &[(&str, &[(&str, Value<'_>)])],
+    ) -> zbus::Result<OwnedObjectPath>;
+
+    fn list_units_by_patterns(
+        &self,
+        states: &[&str],
+        patterns: &[&str],
+    ) -> zbus::Result<Vec<UnitStatus>>;
+
+    fn kill_unit(&self, name: &str, whom: &str, signal: i32) -> zbus::Result<()>;
+}
+
+/// One entry of `ListUnitsByPatterns`: name, description, load, active and sub state, followed
+/// unit, object path, and the queued job's id, type and path.
+type UnitStatus = (
+    String,
+    String,
+    String,
+    String,
+    String,
+    String,
+    OwnedObjectPath,
+    u32,
+    String,
+    OwnedObjectPath,
+);
+
+const SIGTERM: i32 = 15;
+
+/// Moves `pid` into a new transient `app-<id>-<random>.scope` of the user's systemd manager,
+/// so it outlives whoever launched it and is accounted for on its own.
+pub(crate) fn move_to_app_scope(app_id: &str, pid: u32) -> Result<String> {
+    let conn = Connection::session().context("connect to session bus")?;
+    let manager = SystemdManagerProxyBlocking::new(&conn).context("systemd user manager")?;
+
+    let random = RandomState::new().build_hasher().finish();
+    let unit = format!("app-{}-{:016x}.scope", escape_unit_name(app_id), random);
+    let properties = [
+        ("PIDs", Value::from(vec![pid])),
+        ("CollectMode", Value::from("inactive-or-failed")),
+    ];
+    manager
+        .start_transient_unit(&unit, "fail", &properties, &[])
+        .with_context(|| format!("start transient unit {unit}"))?;
+    Ok(unit)
+}
+
+/// Sends SIGTERM to every process of the active `app-*.scope` units started for one of
+/// `app_ids`, whether by [`move_to_app_scope`] or by the desktop. Returns how many scopes were
+/// signalled.
+///
+/// Ids are compared ignoring ASCII case, so a `Code` window finds the scope of `code`. Apps
+/// whose scope is named after an unrelated desktop id are not found.
+pub(crate) fn kill_app_scopes(app_ids: &[&str]) -> Result<usize> {
+    let conn = Connection::session().context("connect to session bus")?;
+    let manager = SystemdManagerProxyBlocking::new(&conn).context("systemd user manager")?;
+
+    let units = manager
+        .list_units_by_patterns(&["active"], &["app-*.scope"])
+        .context("list app scopes")?;
+    let mut killed = 0;
+    for (unit, ..) in units {
+        if app_ids.iter().any(|app_id| is_app_scope(&unit, app_id)) {
+            manager
+                .kill_unit(&unit, "all", SIGTERM)
+                .with_context(|| format!("kill {unit}"))?;
+            killed += 1;
+        }
+    }
+    Ok(killed)
+}
+
+/// Whether `unit` is an `app-<id>-<suffix>.scope` (or `app-<launcher>-<id>-<suffix>.scope`)
+/// unit of `app_id`.
+fn is_app_scope(unit: &str, app_id: &str) -> bool {
+    let needle = format!("-{}-", escape_unit_name(app_id));
+    unit.to_ascii_lowercase()
+        .contains(&needle.to_ascii_lowercase())
+}
+
+/// Escapes a string for use inside a unit name, like `systemd-escape` does. `-` is escaped
+/// as well since it separates the name's components.
+fn escape_unit_name(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for (idx, byte) in name.bytes().enumerate() {
+        match byte {
+            b'.' if idx == 0 => escaped.push_str("\\x2e"),
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'_' | b'.' | b':' => {
+                escaped.push(byte as char)
+            }
+            _ => escaped.push_str(&format!("\\x{byte:02x}")),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn app_scope_matches_id_ignoring_case() {
+        let unit = "app-code-0123456789abcdef.scope";
+        assert!(is_app_scope(unit, "code"));
+        assert!(is_app_scope(unit, "Code"));
+        assert!(is_app_scope(
+            "app-flatpak-org.gnome.Maps-42.scope",
+            "org.gnome.maps"
+        ));
+    }
+
+    #[test]
+    fn app_scope_needs_whole_id() {
+        assert!(!is_app_scope("app-vscode-1.scope", "code"));
+        assert!(!is_app_scope("app-code-oss-1.scope", "code-oss-1"));
+        assert!(!is_app_scope("app-firefox-1.scope", "org.mozilla.firefox"));
+    }
+
+    #[test]
+    fn escape_unit_name_like_systemd_escape() {
+        assert_eq!(escape_unit_name("org.gnome.Maps"), "org.gnome.Maps");
+        assert_eq!(escape_unit_name("code-oss"), "code\\x2doss");
+        assert_eq!(escape_unit_name(".hidden.app"), "\\x2ehidden.app");
+        assert_eq!(escape_unit_name("my app/1"), "my\\x20app\\x2f1");
+        assert_eq!(escape_unit_name("snake_case:2"), "snake_case:2");
+        assert_eq!(escape_unit_name("café"), "caf\\xc3\\xa9");
+    }
+}
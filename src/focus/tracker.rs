@@ -0,0 +1,987 @@
+use rustix::event::{poll, PollFd, PollFlags, Timespec};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeSet,
+    io::ErrorKind,
+    time::{Duration, Instant},
+};
+
+use cosmic_protocols::toplevel_info::v1::client::{
+    zcosmic_toplevel_handle_v1::{
+        Event as CosmicHandleEvent, State as CosmicHandleState, ZcosmicToplevelHandleV1,
+    },
+    zcosmic_toplevel_info_v1::{
+        Event as CosmicInfoEvent, ZcosmicToplevelInfoV1, EVT_TOPLEVEL_OPCODE,
+    },
+};
+use cosmic_protocols::toplevel_management::v1::client::zcosmic_toplevel_manager_v1::ZcosmicToplevelManagerV1;
+use wayland_client::{
+    backend::WaylandError,
+    event_created_child,
+    globals::{registry_queue_init, GlobalList, GlobalListContents},
+    protocol::{wl_output, wl_registry, wl_seat},
+    Connection, Dispatch, EventQueue, Proxy, QueueHandle,
+};
+use wayland_protocols::ext::foreign_toplevel_list::v1::client::{
+    ext_foreign_toplevel_handle_v1::{Event as ForeignToplevelEvent, ExtForeignToplevelHandleV1},
+    ext_foreign_toplevel_list_v1::{
+        Event as ForeignListEvent, ExtForeignToplevelListV1,
+        EVT_TOPLEVEL_OPCODE as FOREIGN_TOPLEVEL_OPCODE,
+    },
+};
+use wayland_protocols::ext::workspace::v1::client::{
+    ext_workspace_group_handle_v1::{Event as WorkspaceGroupEvent, ExtWorkspaceGroupHandleV1},
+    ext_workspace_handle_v1::{
+        Event as WorkspaceEvent, ExtWorkspaceHandleV1, State as WorkspaceState,
+    },
+    ext_workspace_manager_v1::{
+        Event as WorkspaceManagerEvent, ExtWorkspaceManagerV1, EVT_WORKSPACE_GROUP_OPCODE,
+        EVT_WORKSPACE_OPCODE,
+    },
+};
+
+use super::FocusError;
+
+pub type CosmicToplevelInfo = ZcosmicToplevelInfoV1;
+pub type CosmicToplevelHandle = ZcosmicToplevelHandleV1;
+pub type CosmicToplevelManager = ZcosmicToplevelManagerV1;
+pub type ForeignToplevelList = ExtForeignToplevelListV1;
+pub type ForeignToplevelHandle = ExtForeignToplevelHandleV1;
+pub type WorkspaceManager = ExtWorkspaceManagerV1;
+pub type WorkspaceGroupHandle = ExtWorkspaceGroupHandleV1;
+pub type WorkspaceHandle = ExtWorkspaceHandleV1;
+
+/// How long [`ToplevelTracker::discover`] waits for handles that have not finished describing
+/// themselves.
+pub const DEFAULT_DISCOVERY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Order in which windows are listed.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize, Serialize, clap::ValueEnum,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum ToplevelOrder {
+    /// Oldest window first, as the compositor announced them.
+    #[default]
+    Opened,
+    /// Most recently focused window first; windows not focused since the tracker connected
+    /// follow, newest first.
+    Recent,
+}
+
+/// Snapshot of a toplevel window as reported by the compositor.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize, zbus::zvariant::Type)]
+pub struct Toplevel {
+    /// Tracker-assigned identifier, stable for the lifetime of the window.
+    pub id: u64,
+    pub app_id: String,
+    pub title: String,
+    pub activated: bool,
+    pub minimized: bool,
+    pub maximized: bool,
+    pub fullscreen: bool,
+    /// Names of the outputs the window is shown on.
+    pub outputs: Vec<String>,
+    /// Names of the workspaces the window belongs to.
+    pub workspaces: Vec<String>,
+}
+
+#[derive(Clone)]
+pub(crate) struct TrackedToplevel {
+    pub(crate) foreign: Option<ForeignToplevelHandle>,
+    pub(crate) cosmic: Option<CosmicToplevelHandle>,
+    /// Public view of the window. Its `id` doubles as the announcement sequence number that
+    /// gives every app a stable window order; `outputs` and `workspaces` stay empty here and
+    /// are resolved from the handles below when a snapshot is taken.
+    pub(crate) info: Toplevel,
+    pub(crate) outputs: Vec<wl_output::WlOutput>,
+    pub(crate) workspaces: Vec<WorkspaceHandle>,
+    /// Activation serial of the last time this window gained focus; `0` if it has not since
+    /// the tracker connected.
+    pub(crate) last_activated: u64,
+    foreign_done: bool,
+    cosmic_done: bool,
+}
+
+struct TrackedOutput {
+    global: u32,
+    output: wl_output::WlOutput,
+    name: Option<String>,
+}
+
+struct TrackedWorkspace {
+    handle: WorkspaceHandle,
+    name: String,
+    active: bool,
+    group: Option<WorkspaceGroupHandle>,
+}
+
+/// A workspace group and the outputs it is shown on.
+struct TrackedWorkspaceGroup {
+    handle: WorkspaceGroupHandle,
+    outputs: Vec<wl_output::WlOutput>,
+}
+
+/// Decodes the `wl_array` of native-endian u32 values carried by `CosmicHandleEvent::State`.
+fn decode_handle_states(raw: &[u8]) -> impl Iterator<Item = CosmicHandleState> + '_ {
+    raw.chunks_exact(4).filter_map(|chunk| {
+        let value = u32::from_ne_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        CosmicHandleState::try_from(value).ok()
+    })
+}
+
+impl TrackedToplevel {
+    fn apply_states(&mut self, raw: &[u8]) {
+        let info = &mut self.info;
+        info.activated = false;
+        info.minimized = false;
+        info.maximized = false;
+        info.fullscreen = false;
+        for state in decode_handle_states(raw) {
+            match state {
+                CosmicHandleState::Activated => info.activated = true,
+                CosmicHandleState::Minimized => info.minimized = true,
+                CosmicHandleState::Maximized => info.maximized = true,
+                CosmicHandleState::Fullscreen => info.fullscreen = true,
+                _ => {}
+            }
+        }
+    }
+
+    /// Whether every handle of this toplevel has sent its initial `done`.
+    pub(crate) fn is_described(&self) -> bool {
+        (self.foreign.is_none() || self.foreign_done) && (self.cosmic.is_none() || self.cosmic_done)
+    }
+
+    fn matches_foreign(&self, handle: &ForeignToplevelHandle) -> bool {
+        self.foreign
+            .as_ref()
+            .map(|stored| stored.id() == handle.id())
+            .unwrap_or(false)
+    }
+
+    fn matches_cosmic(&self, handle: &CosmicToplevelHandle) -> bool {
+        self.cosmic
+            .as_ref()
+            .map(|stored| stored.id() == handle.id())
+            .unwrap_or(false)
+    }
+}
+
+/// Keeps the compositor's toplevel list in sync.
+///
+/// The tracker is the dispatch state of its event queue: it owns the bound COSMIC and
+/// `ext_foreign_toplevel_list_v1` globals and records every toplevel they announce. Drive
+/// the queue (roundtrips or blocking dispatch) and query the tracker in between.
+pub struct ToplevelTracker {
+    seat: Option<wl_seat::WlSeat>,
+    info: Option<CosmicToplevelInfo>,
+    mgr: Option<CosmicToplevelManager>,
+    foreign_list: Option<ForeignToplevelList>,
+    workspace_manager: Option<WorkspaceManager>,
+    toplevels: Vec<TrackedToplevel>,
+    outputs: Vec<TrackedOutput>,
+    workspaces: Vec<TrackedWorkspace>,
+    workspace_groups: Vec<TrackedWorkspaceGroup>,
+    next_order: u64,
+    activations: u64,
+    changed: bool,
+}
+
+impl ToplevelTracker {
+    /// Connects to the Wayland display named by the environment and binds a fresh tracker.
+    pub fn connect() -> Result<(Connection, EventQueue<Self>, Self), FocusError> {
+        let conn = Connection::connect_to_env().map_err(FocusError::NoCompositor)?;
+        log::debug!("Connected to Wayland display");
+        let (globals, event_queue) = registry_queue_init::<Self>(&conn)
+            .map_err(FocusError::wayland("initialize registry"))?;
+        let tracker = Self::bind(&globals, &event_queue.handle())?;
+        Ok((conn, event_queue, tracker))
+    }
+
+    /// Binds the globals the tracker relies on. Only `zcosmic_toplevel_info_v1` is required;
+    /// without the toplevel manager the tracker can still list windows but not act on them.
+    pub fn bind(globals: &GlobalList, qh: &QueueHandle<Self>) -> Result<Self, FocusError> {
+        let mut tracker = Self {
+            seat: None,
+            info: None,
+            mgr: None,
+            foreign_list: None,
+            workspace_manager: None,
+            toplevels: Vec::new(),
+            outputs: Vec::new(),
+            workspaces: Vec::new(),
+            workspace_groups: Vec::new(),
+            next_order: 0,
+            activations: 0,
+            changed: false,
+        };
+
+        if let Ok(seat) = globals.bind::<wl_seat::WlSeat, _, _>(qh, 1..=8, ()) {
+            log::debug!("Bound wl_seat v{}", seat.version());
+            tracker.seat = Some(seat);
+        } else {
+            log::warn!("No wl_seat available; windows cannot be activated");
+        }
+
+        let info = globals
+            .bind::<CosmicToplevelInfo, _, _>(qh, 1..=3, ())
+            .map_err(|_| FocusError::MissingProtocol("zcosmic_toplevel_info_v1"))?;
+        log::debug!("Bound cosmic_toplevel_info v{}", info.version());
+        if info.version() < 2 {
+            log::warn!(
+                "cosmic_toplevel_info version {} lacks get_cosmic_toplevel; relying on fallback app_id events",
+                info.version()
+            );
+        }
+        tracker.info = Some(info);
+
+        match globals.bind::<CosmicToplevelManager, _, _>(qh, 1..=4, ()) {
+            Ok(mgr) => {
+                log::debug!("Bound cosmic_toplevel_manager v{}", mgr.version());
+                tracker.mgr = Some(mgr);
+            }
+            Err(_) => {
+                log::warn!("cosmic_toplevel_manager unavailable; toplevels are read-only");
+            }
+        }
+
+        match globals.bind::<ForeignToplevelList, _, _>(qh, 1..=1, ()) {
+            Ok(list) => {
+                log::debug!(
+                    "Bound ext_foreign_toplevel_list_v1 v{} for richer metadata",
+                    list.version()
+                );
+                tracker.foreign_list = Some(list);
+            }
+            Err(_) => {
+                log::warn!(
+                    "ext_foreign_toplevel_list_v1 unavailable; relying solely on COSMIC handles"
+                );
+            }
+        }
+
+        match globals.bind::<WorkspaceManager, _, _>(qh, 1..=1, ()) {
+            Ok(manager) => {
+                log::debug!("Bound ext_workspace_manager_v1 v{}", manager.version());
+                tracker.workspace_manager = Some(manager);
+            }
+            Err(_) => {
+                log::warn!("ext_workspace_manager_v1 unavailable; workspaces will not be reported");
+            }
+        }
+
+        let registry = globals.registry();
+        globals.contents().with_list(|list| {
+            for global in list
+                .iter()
+                .filter(|global| global.interface == wl_output::WlOutput::interface().name)
+            {
+                tracker.bind_output(registry, global.name, global.version, qh);
+            }
+        });
+
+        Ok(tracker)
+    }
+
+    /// Processes the initial toplevel announcements.
+    ///
+    /// Returns once the compositor has announced every existing toplevel and each of their
+    /// handles has sent `done`, or when `timeout` expires with some handles still incomplete.
+    pub fn discover(
+        &mut self,
+        event_queue: &mut EventQueue<Self>,
+        timeout: Duration,
+    ) -> Result<(), FocusError> {
+        let deadline = Instant::now() + timeout;
+        // Toplevels are announced as soon as the lists are bound, so one roundtrip is
+        // enough to learn about all of them; only their details may still be in flight.
+        event_queue
+            .roundtrip(self)
+            .map_err(FocusError::wayland("process wayland events"))?;
+
+        loop {
+            let pending = self
+                .toplevels
+                .iter()
+                .filter(|tracked| !tracked.is_described())
+                .count();
+            if pending == 0 {
+                log::debug!("Discovered {} toplevel(s)", self.toplevels.len());
+                return Ok(());
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                log::warn!(
+                    "Toplevel discovery timed out after {:?} with {} handle(s) incomplete",
+                    timeout,
+                    pending
+                );
+                return Ok(());
+            }
+            self.dispatch_timeout(event_queue, deadline - now)?;
+        }
+    }
+
+    /// Dispatches queued events, waiting at most `timeout` for new ones to arrive.
+    pub fn dispatch_timeout(
+        &mut self,
+        event_queue: &mut EventQueue<Self>,
+        timeout: Duration,
+    ) -> Result<usize, FocusError> {
+        let dispatched = event_queue
+            .dispatch_pending(self)
+            .map_err(FocusError::wayland("dispatch wayland events"))?;
+        if dispatched > 0 {
+            return Ok(dispatched);
+        }
+
+        event_queue
+            .flush()
+            .map_err(FocusError::wayland("flush wayland requests"))?;
+        if let Some(guard) = event_queue.prepare_read() {
+            let ready = {
+                let fd = guard.connection_fd();
+                let mut fds = [PollFd::new(&fd, PollFlags::IN | PollFlags::ERR)];
+                let timeout = Timespec {
+                    tv_sec: timeout.as_secs() as _,
+                    tv_nsec: timeout.subsec_nanos() as _,
+                };
+                match poll(&mut fds, Some(&timeout)) {
+                    Ok(ready) => ready,
+                    Err(rustix::io::Errno::INTR) => 0,
+                    Err(err) => return Err(FocusError::wayland("poll wayland socket")(err)),
+                }
+            };
+            if ready > 0 {
+                match guard.read() {
+                    Ok(_) => {}
+                    Err(WaylandError::Io(err)) if err.kind() == ErrorKind::WouldBlock => {}
+                    Err(err) => return Err(FocusError::wayland("read wayland events")(err)),
+                }
+            }
+        }
+
+        event_queue
+            .dispatch_pending(self)
+            .map_err(FocusError::wayland("dispatch wayland events"))
+    }
+
+    pub fn seat(&self) -> Option<&wl_seat::WlSeat> {
+        self.seat.as_ref()
+    }
+
+    pub fn manager(&self) -> Option<&CosmicToplevelManager> {
+        self.mgr.as_ref()
+    }
+
+    /// App ids of all known toplevels, deduplicated case-insensitively, most recently focused
+    /// first (see [`ToplevelOrder::Recent`]).
+    pub fn running_apps(&self) -> Vec<String> {
+        let mut seen = BTreeSet::new();
+        let mut apps = Vec::new();
+        for tracked in self.ordered(ToplevelOrder::Recent) {
+            let app_id = &tracked.info.app_id;
+            if !app_id.is_empty() && seen.insert(app_id.to_lowercase()) {
+                apps.push(app_id.clone());
+            }
+        }
+        apps
+    }
+
+    /// Returns whether the toplevel list changed since the last call.
+    pub fn take_changed(&mut self) -> bool {
+        std::mem::take(&mut self.changed)
+    }
+
+    /// Snapshots of all known toplevels in announcement order.
+    pub fn toplevels(&self) -> Vec<Toplevel> {
+        self.toplevels_by(ToplevelOrder::Opened)
+    }
+
+    /// Snapshots of all known toplevels in the given order.
+    pub fn toplevels_by(&self, order: ToplevelOrder) -> Vec<Toplevel> {
+        self.ordered(order)
+            .into_iter()
+            .map(|tracked| self.snapshot(tracked))
+            .collect()
+    }
+
+    pub(crate) fn ordered(&self, order: ToplevelOrder) -> Vec<&TrackedToplevel> {
+        let mut ordered: Vec<&TrackedToplevel> = self.toplevels.iter().collect();
+        if order == ToplevelOrder::Recent {
+            ordered.sort_by_key(|tracked| {
+                std::cmp::Reverse((tracked.last_activated, tracked.info.id))
+            });
+        }
+        ordered
+    }
+
+    pub(crate) fn tracked(&self) -> &[TrackedToplevel] {
+        &self.toplevels
+    }
+
+    pub(crate) fn snapshot(&self, tracked: &TrackedToplevel) -> Toplevel {
+        let mut toplevel = tracked.info.clone();
+        toplevel.outputs = tracked
+            .outputs
+            .iter()
+            .map(|output| self.output_name(output))
+            .collect();
+        toplevel.workspaces = tracked
+            .workspaces
+            .iter()
+            .filter_map(|handle| {
+                self.workspaces
+                    .iter()
+                    .find(|workspace| workspace.handle == *handle)
+                    .map(|workspace| workspace.name.clone())
+            })
+            .collect();
+        toplevel
+    }
+
+    pub(crate) fn output_by_name(&self, name: &str) -> Option<&wl_output::WlOutput> {
+        self.outputs
+            .iter()
+            .find(|tracked| tracked.name.as_deref() == Some(name))
+            .map(|tracked| &tracked.output)
+    }
+
+    pub(crate) fn is_workspace_active(&self, handle: &WorkspaceHandle) -> bool {
+        self.workspaces
+            .iter()
+            .any(|workspace| workspace.handle == *handle && workspace.active)
+    }
+
+    /// Workspaces currently shown, one per output.
+    pub(crate) fn active_workspaces(&self) -> impl Iterator<Item = &WorkspaceHandle> {
+        self.workspaces
+            .iter()
+            .filter(|workspace| workspace.active)
+            .map(|workspace| &workspace.handle)
+    }
+
+    /// The workspace currently shown on `output`.
+    pub(crate) fn active_workspace_on(
+        &self,
+        output: &wl_output::WlOutput,
+    ) -> Option<&WorkspaceHandle> {
+        let groups: Vec<&WorkspaceGroupHandle> = self
+            .workspace_groups
+            .iter()
+            .filter(|group| group.outputs.contains(output))
+            .map(|group| &group.handle)
+            .collect();
+        self.workspaces
+            .iter()
+            .find(|workspace| {
+                workspace.active
+                    && workspace
+                        .group
+                        .as_ref()
+                        .is_some_and(|group| groups.contains(&group))
+            })
+            .map(|workspace| &workspace.handle)
+    }
+
+    pub(crate) fn outputs(&self) -> impl Iterator<Item = &wl_output::WlOutput> {
+        self.outputs.iter().map(|tracked| &tracked.output)
+    }
+
+    fn output_name(&self, output: &wl_output::WlOutput) -> String {
+        self.outputs
+            .iter()
+            .find(|tracked| tracked.output == *output)
+            .and_then(|tracked| tracked.name.clone())
+            .unwrap_or_else(|| format!("output-{}", output.id().protocol_id()))
+    }
+
+    fn bind_output(
+        &mut self,
+        registry: &wl_registry::WlRegistry,
+        global: u32,
+        version: u32,
+        qh: &QueueHandle<Self>,
+    ) {
+        let output = registry.bind::<wl_output::WlOutput, _, _>(global, version.min(4), qh, ());
+        log::debug!("Bound wl_output v{} (global {})", output.version(), global);
+        self.outputs.push(TrackedOutput {
+            global,
+            output,
+            name: None,
+        });
+    }
+
+    fn push_toplevel(
+        &mut self,
+        foreign: Option<ForeignToplevelHandle>,
+        cosmic: Option<CosmicToplevelHandle>,
+    ) -> &mut TrackedToplevel {
+        let id = self.next_order;
+        self.next_order += 1;
+        self.changed = true;
+        self.toplevels.push(TrackedToplevel {
+            foreign,
+            cosmic,
+            info: Toplevel {
+                id,
+                ..Default::default()
+            },
+            outputs: Vec::new(),
+            workspaces: Vec::new(),
+            foreign_done: false,
+            last_activated: 0,
+            cosmic_done: false,
+        });
+        self.toplevels.last_mut().expect("toplevel just pushed")
+    }
+
+    fn foreign_entry(&mut self, handle: &ForeignToplevelHandle) -> Option<&mut TrackedToplevel> {
+        self.toplevels
+            .iter_mut()
+            .find(|tracked| tracked.matches_foreign(handle))
+    }
+
+    /// Whether COSMIC handles are announced on their own, as by `cosmic_toplevel_info` v1,
+    /// rather than requested for each foreign toplevel.
+    fn standalone_cosmic_handles(&self) -> bool {
+        self.foreign_list.is_none() || self.info.as_ref().is_some_and(|info| info.version() < 2)
+    }
+
+    /// Finds the toplevel behind a COSMIC handle. Standalone handles are tracked on first
+    /// sight; otherwise a handle without an entry belongs to a window that already closed.
+    fn cosmic_entry(&mut self, handle: &CosmicToplevelHandle) -> Option<&mut TrackedToplevel> {
+        match self
+            .toplevels
+            .iter()
+            .position(|tracked| tracked.matches_cosmic(handle))
+        {
+            Some(idx) => Some(&mut self.toplevels[idx]),
+            None if self.standalone_cosmic_handles() => {
+                log::debug!("Tracking standalone cosmic handle {}", handle.id());
+                Some(self.push_toplevel(None, Some(handle.clone())))
+            }
+            None => None,
+        }
+    }
+
+    fn remove_by_foreign(&mut self, handle: &ForeignToplevelHandle) {
+        log::debug!("Foreign toplevel {} closed", handle.id());
+        match self
+            .toplevels
+            .iter()
+            .position(|tracked| tracked.matches_foreign(handle))
+        {
+            Some(idx) => self.remove(idx),
+            None => handle.destroy(),
+        }
+    }
+
+    fn remove_by_cosmic(&mut self, handle: &CosmicToplevelHandle) {
+        log::debug!("Cosmic toplevel {} closed", handle.id());
+        match self
+            .toplevels
+            .iter()
+            .position(|tracked| tracked.matches_cosmic(handle))
+        {
+            Some(idx) => self.remove(idx),
+            None => handle.destroy(),
+        }
+    }
+
+    /// Forgets a closed toplevel, destroying both of its handles so long-lived trackers do
+    /// not accumulate them.
+    fn remove(&mut self, idx: usize) {
+        let tracked = self.toplevels.remove(idx);
+        if let Some(foreign) = tracked.foreign {
+            foreign.destroy();
+        }
+        if let Some(cosmic) = tracked.cosmic {
+            cosmic.destroy();
+        }
+        self.changed = true;
+    }
+}
+
+impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for ToplevelTracker {
+    fn event(
+        state: &mut Self,
+        registry: &wl_registry::WlRegistry,
+        event: wl_registry::Event,
+        _data: &GlobalListContents,
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            wl_registry::Event::Global {
+                name,
+                interface,
+                version,
+            } if interface == wl_output::WlOutput::interface().name => {
+                state.bind_output(registry, name, version, qh);
+            }
+            wl_registry::Event::GlobalRemove { name } => {
+                if let Some(idx) = state
+                    .outputs
+                    .iter()
+                    .position(|tracked| tracked.global == name)
+                {
+                    let tracked = state.outputs.remove(idx);
+                    log::debug!("Output {:?} removed", tracked.name);
+                    if tracked.output.version() >= 3 {
+                        tracked.output.release();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<wl_output::WlOutput, ()> for ToplevelTracker {
+    fn event(
+        state: &mut Self,
+        output: &wl_output::WlOutput,
+        event: wl_output::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        if let wl_output::Event::Name { name } = event {
+            if let Some(tracked) = state
+                .outputs
+                .iter_mut()
+                .find(|tracked| tracked.output == *output)
+            {
+                tracked.name = Some(name);
+                state.changed = true;
+            }
+        }
+    }
+}
+
+impl Dispatch<wl_seat::WlSeat, ()> for ToplevelTracker {
+    fn event(
+        state: &mut Self,
+        seat: &wl_seat::WlSeat,
+        _event: wl_seat::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        if state.seat.is_none() {
+            state.seat = Some(seat.clone());
+        }
+    }
+}
+
+impl Dispatch<ForeignToplevelList, ()> for ToplevelTracker {
+    fn event(
+        state: &mut Self,
+        _list: &ForeignToplevelList,
+        event: ForeignListEvent,
+        _data: &(),
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            ForeignListEvent::Toplevel { toplevel } => {
+                let cosmic_handle = state.info.as_ref().and_then(|info| {
+                    if info.version() >= 2 {
+                        Some(info.get_cosmic_toplevel(&toplevel, qh, ()))
+                    } else {
+                        None
+                    }
+                });
+                let cosmic_id = cosmic_handle.as_ref().map(|handle| handle.id());
+                log::debug!(
+                    "Foreign toplevel {} announced (cosmic handle {:?})",
+                    toplevel.id(),
+                    cosmic_id
+                );
+
+                state.push_toplevel(Some(toplevel.clone()), cosmic_handle);
+            }
+            ForeignListEvent::Finished => {}
+            _ => {}
+        }
+    }
+
+    event_created_child!(
+        ToplevelTracker,
+        ForeignToplevelList,
+        [
+            FOREIGN_TOPLEVEL_OPCODE => (ForeignToplevelHandle, ())
+        ]
+    );
+}
+
+impl Dispatch<ForeignToplevelHandle, ()> for ToplevelTracker {
+    fn event(
+        state: &mut Self,
+        toplevel: &ForeignToplevelHandle,
+        event: ForeignToplevelEvent,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            ForeignToplevelEvent::AppId { app_id } => {
+                if let Some(tracked) = state.foreign_entry(toplevel) {
+                    log::debug!(
+                        "Foreign toplevel {} reports app_id '{}'",
+                        toplevel.id(),
+                        app_id
+                    );
+                    tracked.info.app_id = app_id;
+                    state.changed = true;
+                }
+            }
+            ForeignToplevelEvent::Title { title } => {
+                if let Some(tracked) = state.foreign_entry(toplevel) {
+                    tracked.info.title = title;
+                    state.changed = true;
+                }
+            }
+            ForeignToplevelEvent::Done => {
+                if let Some(tracked) = state.foreign_entry(toplevel) {
+                    tracked.foreign_done = true;
+                    // May complete the window's description, making it reportable.
+                    state.changed = true;
+                }
+            }
+            ForeignToplevelEvent::Closed => {
+                state.remove_by_foreign(toplevel);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<CosmicToplevelHandle, ()> for ToplevelTracker {
+    fn event(
+        state: &mut Self,
+        handle: &CosmicToplevelHandle,
+        event: CosmicHandleEvent,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        if let CosmicHandleEvent::Closed = event {
+            state.remove_by_cosmic(handle);
+            return;
+        }
+        let serial = state.activations + 1;
+        let Some(tracked) = state.cosmic_entry(handle) else {
+            return;
+        };
+        match event {
+            CosmicHandleEvent::AppId { app_id } => {
+                log::debug!("Cosmic handle {} reports app_id '{}'", handle.id(), app_id);
+                tracked.info.app_id = app_id;
+            }
+            CosmicHandleEvent::Title { title } => {
+                tracked.info.title = title;
+            }
+            CosmicHandleEvent::State { state: raw } => {
+                let was_activated = tracked.info.activated;
+                tracked.apply_states(&raw);
+                if tracked.info.activated && !was_activated {
+                    tracked.last_activated = serial;
+                    state.activations = serial;
+                }
+            }
+            CosmicHandleEvent::OutputEnter { output } => {
+                if !tracked.outputs.contains(&output) {
+                    tracked.outputs.push(output);
+                }
+            }
+            CosmicHandleEvent::OutputLeave { output } => {
+                tracked.outputs.retain(|entered| *entered != output);
+            }
+            CosmicHandleEvent::ExtWorkspaceEnter { workspace } => {
+                if !tracked.workspaces.contains(&workspace) {
+                    tracked.workspaces.push(workspace);
+                }
+            }
+            CosmicHandleEvent::ExtWorkspaceLeave { workspace } => {
+                tracked.workspaces.retain(|entered| *entered != workspace);
+            }
+            CosmicHandleEvent::Done => {
+                tracked.cosmic_done = true;
+            }
+            _ => return,
+        }
+        state.changed = true;
+    }
+}
+
+impl Dispatch<CosmicToplevelInfo, ()> for ToplevelTracker {
+    fn event(
+        _state: &mut Self,
+        _info: &CosmicToplevelInfo,
+        _event: CosmicInfoEvent,
+        _data: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+
+    event_created_child!(
+        ToplevelTracker,
+        CosmicToplevelInfo,
+        [
+            EVT_TOPLEVEL_OPCODE => (CosmicToplevelHandle, ())
+        ]
+    );
+}
+
+impl Dispatch<CosmicToplevelManager, ()> for ToplevelTracker {
+    fn event(
+        _: &mut Self,
+        _: &CosmicToplevelManager,
+        _: <CosmicToplevelManager as Proxy>::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<WorkspaceManager, ()> for ToplevelTracker {
+    fn event(
+        state: &mut Self,
+        _manager: &WorkspaceManager,
+        event: WorkspaceManagerEvent,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            WorkspaceManagerEvent::WorkspaceGroup { workspace_group } => {
+                state.workspace_groups.push(TrackedWorkspaceGroup {
+                    handle: workspace_group,
+                    outputs: Vec::new(),
+                });
+            }
+            WorkspaceManagerEvent::Workspace { workspace } => {
+                state.workspaces.push(TrackedWorkspace {
+                    handle: workspace,
+                    name: String::new(),
+                    active: false,
+                    group: None,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    event_created_child!(
+        ToplevelTracker,
+        WorkspaceManager,
+        [
+            EVT_WORKSPACE_GROUP_OPCODE => (WorkspaceGroupHandle, ()),
+            EVT_WORKSPACE_OPCODE => (WorkspaceHandle, ())
+        ]
+    );
+}
+
+impl Dispatch<WorkspaceGroupHandle, ()> for ToplevelTracker {
+    fn event(
+        state: &mut Self,
+        group: &WorkspaceGroupHandle,
+        event: WorkspaceGroupEvent,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            WorkspaceGroupEvent::OutputEnter { output } => {
+                if let Some(tracked) = state
+                    .workspace_groups
+                    .iter_mut()
+                    .find(|tracked| tracked.handle == *group)
+                {
+                    if !tracked.outputs.contains(&output) {
+                        tracked.outputs.push(output);
+                    }
+                }
+            }
+            WorkspaceGroupEvent::OutputLeave { output } => {
+                if let Some(tracked) = state
+                    .workspace_groups
+                    .iter_mut()
+                    .find(|tracked| tracked.handle == *group)
+                {
+                    tracked.outputs.retain(|entered| *entered != output);
+                }
+            }
+            WorkspaceGroupEvent::WorkspaceEnter { workspace } => {
+                if let Some(tracked) = state
+                    .workspaces
+                    .iter_mut()
+                    .find(|tracked| tracked.handle == workspace)
+                {
+                    tracked.group = Some(group.clone());
+                }
+            }
+            WorkspaceGroupEvent::WorkspaceLeave { workspace } => {
+                if let Some(tracked) = state.workspaces.iter_mut().find(|tracked| {
+                    tracked.handle == workspace && tracked.group.as_ref() == Some(group)
+                }) {
+                    tracked.group = None;
+                }
+            }
+            WorkspaceGroupEvent::Removed => {
+                state
+                    .workspace_groups
+                    .retain(|tracked| tracked.handle != *group);
+                group.destroy();
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<WorkspaceHandle, ()> for ToplevelTracker {
+    fn event(
+        state: &mut Self,
+        handle: &WorkspaceHandle,
+        event: WorkspaceEvent,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        if let WorkspaceEvent::Removed = event {
+            state
+                .workspaces
+                .retain(|workspace| workspace.handle != *handle);
+            handle.destroy();
+            state.changed = true;
+            return;
+        }
+
+        let Some(workspace) = state
+            .workspaces
+            .iter_mut()
+            .find(|workspace| workspace.handle == *handle)
+        else {
+            return;
+        };
+        match event {
+            WorkspaceEvent::Name { name } => {
+                workspace.name = name;
+                state.changed = true;
+            }
+            WorkspaceEvent::State { state: flags } => {
+                workspace.active = flags
+                    .into_result()
+                    .map(|flags| flags.contains(WorkspaceState::Active))
+                    .unwrap_or(false);
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,365 @@
+use futures::{
+    channel::{mpsc, oneshot},
+    ready, Stream, StreamExt,
+};
+use smithay_client_toolkit::reexports::{
+    calloop::{
+        ping::{self, Ping, PingSource},
+        timer::{TimeoutAction, Timer},
+        EventLoop, LoopHandle, RegistrationToken,
+    },
+    calloop_wayland_source::WaylandSource,
+};
+use std::{
+    pin::Pin,
+    sync::mpsc::{sync_channel, Sender},
+    task::{Context, Poll},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+use wayland_client::{Connection, EventQueue};
+
+use super::{
+    event::{self, ToplevelEvent},
+    FocusError, Toplevel, ToplevelTracker,
+};
+
+/// First delay before the watcher tries to reconnect to the compositor.
+const RECONNECT_DELAY: Duration = Duration::from_millis(250);
+/// Longest delay between reconnection attempts.
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(10);
+
+/// Where the watcher delivers its events; returns `false` once nobody is listening.
+type EventSink = Box<dyn FnMut(ToplevelEvent) -> bool + Send>;
+/// Told whether the first connection could be made.
+type ReadySignal = Box<dyn FnOnce(Result<(), FocusError>) + Send>;
+
+/// A watcher started by [`watch_toplevels`], running on its own thread.
+///
+/// Dropping the handle stops the watcher and closes its Wayland connection. It also stops by
+/// itself once the receiving end of its channel is gone.
+pub struct WatchHandle {
+    stop: Ping,
+    thread: Option<JoinHandle<Result<(), FocusError>>>,
+}
+
+impl WatchHandle {
+    /// Stops the watcher and waits for its thread, returning how it ended. The thread has
+    /// already logged a failure by then.
+    pub fn stop(mut self) -> Result<(), FocusError> {
+        self.stop.ping();
+        self.join()
+    }
+
+    /// Asks the watcher to stop without waiting for its thread.
+    fn cancel(mut self) {
+        self.stop.ping();
+        self.thread = None;
+    }
+
+    fn join(&mut self) -> Result<(), FocusError> {
+        match self.thread.take() {
+            Some(thread) => thread.join().unwrap_or(Ok(())),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for WatchHandle {
+    fn drop(&mut self) {
+        self.stop.ping();
+        // The thread logged how it ended.
+        let _ = self.join();
+    }
+}
+
+/// Starts a thread that sends an [`ToplevelEvent::Added`] for every window (and
+/// [`ToplevelEvent::Activated`] for the focused one), then the events of every change.
+///
+/// Windows are reported once the compositor has finished describing them, and changes that
+/// leave every window as it was send nothing. When the connection breaks, e.g. because the
+/// compositor restarted, [`ToplevelEvent::Disconnected`] is sent and the watcher reconnects
+/// with increasing delays, announcing the windows afresh. Only failing to connect in the first
+/// place returns an error.
+///
+/// Each connection waits up to `discovery_timeout` for the compositor to describe the windows.
+pub fn watch_toplevels(
+    sender: Sender<ToplevelEvent>,
+    discovery_timeout: Duration,
+) -> Result<WatchHandle, FocusError> {
+    let (ready, connected) = sync_channel(1);
+    let watcher = spawn(
+        Box::new(move |event| sender.send(event).is_ok()),
+        Box::new(move |result| {
+            let _ = ready.send(result);
+        }),
+        false,
+        discovery_timeout,
+    )?;
+    connected.recv().unwrap_or(Ok(()))?;
+    Ok(watcher)
+}
+
+/// The events of [`watch_toplevels`] as an async stream, for consumers that must not block
+/// their executor. Dropping it stops the watcher; it ends early only if the watcher fails.
+pub struct ToplevelStream {
+    events: mpsc::UnboundedReceiver<ToplevelEvent>,
+    watcher: Option<WatchHandle>,
+}
+
+/// Like [`watch_toplevels`], but connects on the watcher thread and hands the events out as
+/// a [`ToplevelStream`]. A compositor that cannot be reached yet is retried like a lost
+/// one, so the stream starts with no events instead of failing.
+pub async fn toplevel_stream(discovery_timeout: Duration) -> Result<ToplevelStream, FocusError> {
+    let (sender, events) = mpsc::unbounded();
+    let (ready, connected) = oneshot::channel();
+    let watcher = spawn(
+        Box::new(move |event| sender.unbounded_send(event).is_ok()),
+        Box::new(move |result| {
+            let _ = ready.send(result);
+        }),
+        true,
+        discovery_timeout,
+    )?;
+    connected.await.unwrap_or(Ok(()))?;
+    Ok(ToplevelStream {
+        events,
+        watcher: Some(watcher),
+    })
+}
+
+impl Stream for ToplevelStream {
+    type Item = ToplevelEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<ToplevelEvent>> {
+        let event = ready!(self.events.poll_next_unpin(cx));
+        if event.is_none() {
+            // The watcher dropped its sender on the way out and logs how it ended; joining
+            // here would block the executor.
+            if let Some(watcher) = self.watcher.take() {
+                watcher.cancel();
+            }
+        }
+        Poll::Ready(event)
+    }
+}
+
+impl Drop for ToplevelStream {
+    fn drop(&mut self) {
+        if let Some(watcher) = self.watcher.take() {
+            watcher.cancel();
+        }
+    }
+}
+
+/// Starts the watcher thread, which calls `ready` once the first connection is up or with
+/// the reason it could not be made. With `retry`, a failed first connection is retried
+/// instead.
+fn spawn(
+    sink: EventSink,
+    ready: ReadySignal,
+    retry: bool,
+    discovery_timeout: Duration,
+) -> Result<WatchHandle, FocusError> {
+    let (stop, stopped) =
+        ping::make_ping().map_err(FocusError::wayland("create watcher stop signal"))?;
+    let thread = thread::spawn(move || {
+        let result = run(sink, stopped, ready, retry, discovery_timeout);
+        if let Err(err) = &result {
+            log::error!("Toplevel watcher failed: {err}");
+        }
+        result
+    });
+    Ok(WatchHandle {
+        stop,
+        thread: Some(thread),
+    })
+}
+
+/// Connects and discovers the existing windows. They are still marked as changed, so the
+/// first report announces them.
+fn connect(
+    discovery_timeout: Duration,
+) -> Result<(Connection, EventQueue<ToplevelTracker>, ToplevelTracker), FocusError> {
+    let (conn, mut event_queue, mut tracker) = ToplevelTracker::connect()?;
+    tracker.discover(&mut event_queue, discovery_timeout)?;
+    Ok((conn, event_queue, tracker))
+}
+
+/// Snapshots of the windows the compositor has finished describing, in announcement order.
+fn described_toplevels(tracker: &ToplevelTracker) -> Vec<Toplevel> {
+    tracker
+        .tracked()
+        .iter()
+        .filter(|tracked| tracked.is_described())
+        .map(|tracked| tracker.snapshot(tracked))
+        .collect()
+}
+
+struct Watcher {
+    sink: EventSink,
+    /// `None` while disconnected.
+    tracker: Option<ToplevelTracker>,
+    wayland: Option<RegistrationToken>,
+    /// The windows as last reported.
+    known: Vec<Toplevel>,
+    stopped: bool,
+    /// How long each connection waits for the windows to be described.
+    discovery_timeout: Duration,
+}
+
+impl Watcher {
+    fn send(&mut self, event: ToplevelEvent) {
+        if !(self.sink)(event) {
+            log::debug!("Toplevel watcher receiver is gone; stopping");
+            self.stopped = true;
+        }
+    }
+
+    /// Sends the events of whatever changed since the last report.
+    fn report(&mut self) {
+        let Some(tracker) = self.tracker.as_mut() else {
+            return;
+        };
+        if !tracker.take_changed() {
+            return;
+        }
+        let current = described_toplevels(tracker);
+        for event in event::diff(&self.known, &current) {
+            self.send(event);
+        }
+        self.known = current;
+    }
+
+    /// Starts watching a fresh connection.
+    fn attach(
+        &mut self,
+        handle: &LoopHandle<'static, Watcher>,
+        conn: Connection,
+        event_queue: EventQueue<ToplevelTracker>,
+        tracker: ToplevelTracker,
+    ) -> Result<(), FocusError> {
+        let token = handle
+            .insert_source(
+                WaylandSource::new(conn, event_queue),
+                |_, queue, watcher| {
+                    let Some(tracker) = watcher.tracker.as_mut() else {
+                        return Ok(0);
+                    };
+                    let dispatched = queue.dispatch_pending(tracker);
+                    watcher.report();
+                    dispatched
+                },
+            )
+            .map_err(|err| FocusError::wayland("watch wayland events")(err.error))?;
+        self.wayland = Some(token);
+        self.tracker = Some(tracker);
+        // Window ids restart with every connection.
+        self.known.clear();
+        self.report();
+        Ok(())
+    }
+
+    /// Drops the broken connection and tells the receiver every window is gone.
+    fn detach(&mut self, handle: &LoopHandle<'static, Watcher>) {
+        if let Some(token) = self.wayland.take() {
+            handle.remove(token);
+        }
+        self.tracker = None;
+        self.known.clear();
+        self.send(ToplevelEvent::Disconnected);
+    }
+}
+
+fn run(
+    sink: EventSink,
+    stopped: PingSource,
+    ready: ReadySignal,
+    retry: bool,
+    discovery_timeout: Duration,
+) -> Result<(), FocusError> {
+    let mut watcher = Watcher {
+        sink,
+        tracker: None,
+        wayland: None,
+        known: Vec::new(),
+        stopped: false,
+        discovery_timeout,
+    };
+    let mut event_loop = match start(&mut watcher, stopped, retry) {
+        Ok(event_loop) => {
+            ready(Ok(()));
+            event_loop
+        }
+        Err(err) => {
+            ready(Err(err));
+            return Ok(());
+        }
+    };
+    let handle = event_loop.handle();
+
+    while !watcher.stopped {
+        if let Err(err) = event_loop.dispatch(None, &mut watcher) {
+            if watcher.wayland.is_none() {
+                return Err(FocusError::wayland("run watcher event loop")(err));
+            }
+            log::warn!("Lost the compositor connection ({err}); reconnecting");
+            watcher.detach(&handle);
+            reconnect(&handle)?;
+        }
+    }
+    Ok(())
+}
+
+/// Sets up the event loop and makes the first connection, or with `retry` schedules it when
+/// the compositor cannot be reached.
+fn start(
+    watcher: &mut Watcher,
+    stopped: PingSource,
+    retry: bool,
+) -> Result<EventLoop<'static, Watcher>, FocusError> {
+    let event_loop: EventLoop<'static, Watcher> =
+        EventLoop::try_new().map_err(FocusError::wayland("create watcher event loop"))?;
+    let handle = event_loop.handle();
+    handle
+        .insert_source(stopped, |_, _, watcher| watcher.stopped = true)
+        .map_err(|err| FocusError::wayland("watch stop signal")(err.error))?;
+    let attached = connect(watcher.discovery_timeout).and_then(|(conn, event_queue, tracker)| {
+        watcher.attach(&handle, conn, event_queue, tracker)
+    });
+    match attached {
+        Ok(()) => {}
+        Err(err) if retry => {
+            log::warn!("Cannot reach the compositor yet ({err}); retrying");
+            reconnect(&handle)?;
+        }
+        Err(err) => return Err(err),
+    }
+    Ok(event_loop)
+}
+
+/// Retries connecting from a timer, doubling the delay between attempts.
+fn reconnect(handle: &LoopHandle<'static, Watcher>) -> Result<(), FocusError> {
+    let mut delay = RECONNECT_DELAY;
+    let loop_handle = handle.clone();
+    handle
+        .insert_source(Timer::from_duration(delay), move |_, _, watcher| {
+            let attached =
+                connect(watcher.discovery_timeout).and_then(|(conn, event_queue, tracker)| {
+                    watcher.attach(&loop_handle, conn, event_queue, tracker)
+                });
+            match attached {
+                Ok(()) => {
+                    log::info!("Connected to the compositor");
+                    TimeoutAction::Drop
+                }
+                Err(err) => {
+                    delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+                    log::debug!("Reconnecting failed: {err}; retrying in {delay:?}");
+                    TimeoutAction::ToDuration(delay)
+                }
+            }
+        })
+        .map_err(|err| FocusError::wayland("schedule reconnect")(err.error))?;
+    Ok(())
+}
@@ -1,23 +1,411 @@
-use anyhow::Result;
-use clap::Parser;
-use cosmic_app_focus::focus;
+use clap::{Args, Parser, Subcommand};
+use cosmic_app_focus::focus::{
+    self, FocusError, FocusErrorKind, FocusOptions, FocusPolicy, MatchMode, RunningApps, Toplevel,
+    ToplevelOrder,
+};
+use serde::Serialize;
+use std::{
+    env,
+    ffi::{OsStr, OsString},
+    process::ExitCode,
+    sync::mpsc,
+    time::Duration,
+};
 
 /// Launch or focus an application by app-id / desktop-id (ex: org.mozilla.firefox or firefox)
+///
+/// `cosmic-app-focus <APP_ID>` is shorthand for `cosmic-app-focus focus <APP_ID>`.
 #[derive(Parser, Debug)]
-#[command(author, version, about)]
-struct Args {
+#[command(
+    author,
+    version,
+    about,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    focus: FocusArgs,
+    /// Increase logging verbosity (-v, -vv)
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Focus the app, launching it if it has no window
+    Focus(FocusArgs),
+    /// Start a new instance of the app, even if it is already running
+    Launch(LaunchArgs),
+    /// List open windows
+    List(ListArgs),
+    /// Switch back to the app that was focused before the current one (needs the daemon)
+    Previous,
+    /// Print the running apps every time they change
+    Watch(WatchArgs),
+    /// Ask every window of the app to close
+    Close(CloseArgs),
+    /// Minimize every window of the app
+    Minimize(TargetArgs),
+    /// Show the app's desktop entry and open windows
+    Info(TargetArgs),
+    /// Keep one Wayland connection open and serve focus requests on a Unix socket
+    Daemon(DiscoveryArgs),
+}
+
+/// How long to wait for the compositor to describe the open windows.
+#[derive(Args, Debug)]
+struct DiscoveryArgs {
+    /// Milliseconds to wait for the compositor to describe running windows
+    #[arg(long, value_name = "MS")]
+    discovery_timeout: Option<u64>,
+}
+
+impl DiscoveryArgs {
+    fn timeout(&self) -> Option<Duration> {
+        self.discovery_timeout.map(Duration::from_millis)
+    }
+
+    fn timeout_or_default(&self) -> Duration {
+        self.timeout().unwrap_or(focus::DEFAULT_DISCOVERY_TIMEOUT)
+    }
+}
+
+/// Which app a command acts on.
+#[derive(Args, Debug)]
+struct TargetArgs {
     /// App ID (Wayland app_id or desktop file ID)
-    app_id: String,
-    /// Command to launch if not running (default: gtk-launch <app_id>)
+    #[arg(required = true)]
+    app_id: Option<String>,
+    /// How running windows are matched against the app id (or --pattern)
+    #[arg(long = "match", value_enum, default_value_t = MatchMode::Suffix)]
+    match_mode: MatchMode,
+    /// Pattern to match instead of the app id (glob, regex or desktop id depending on --match)
+    #[arg(long)]
+    pattern: Option<String>,
+    #[command(flatten)]
+    discovery: DiscoveryArgs,
+}
+
+impl TargetArgs {
+    fn app_id(&self) -> &str {
+        self.app_id.as_deref().unwrap_or_default()
+    }
+
+    fn options(&self) -> FocusOptions {
+        FocusOptions {
+            match_mode: self.match_mode,
+            pattern: self.pattern.clone(),
+            discovery_timeout: self.discovery.timeout(),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+struct LaunchArgs {
+    #[command(flatten)]
+    target: TargetArgs,
+    /// Shell command to launch with (default: the app's desktop entry)
     #[arg(long)]
     launch_cmd: Option<String>,
-    /// Increase logging verbosity (-v, -vv)
-    #[arg(short, long, action = clap::ArgAction::Count)]
-    verbose: u8,
+    /// xdg-activation token handed to a launched app (never taken from the environment)
+    #[arg(long, value_name = "TOKEN")]
+    activation_token: Option<String>,
+    /// After launching, wait for the app's window and focus it
+    #[arg(long)]
+    wait: bool,
+    /// Milliseconds --wait gives the app to open a window before failing
+    #[arg(long, value_name = "MS", default_value_t = 10_000, requires = "wait")]
+    wait_timeout: u64,
+}
+
+impl LaunchArgs {
+    fn options(&self) -> FocusOptions {
+        FocusOptions {
+            launch_cmd: self.launch_cmd.clone(),
+            activation_token: self.activation_token.clone(),
+            wait: self.wait.then(|| Duration::from_millis(self.wait_timeout)),
+            ..self.target.options()
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+struct FocusArgs {
+    #[command(flatten)]
+    launch: LaunchArgs,
+    /// Minimize the app if it is already focused (show/hide toggle)
+    #[arg(long)]
+    minimize_on_repeat: bool,
+    /// Which window to prefer when the app has several
+    #[arg(long, value_enum, default_value_t = FocusPolicy::CurrentWorkspace)]
+    policy: FocusPolicy,
+    /// Output to treat as current (defaults to the one holding focus)
+    #[arg(long)]
+    output: Option<String>,
+    /// Move the window to the current workspace and output instead of switching to it
+    #[arg(long)]
+    summon: bool,
+    /// Run in this process even if a focus daemon is listening
+    #[arg(long)]
+    no_daemon: bool,
+}
+
+impl FocusArgs {
+    fn options(&self) -> FocusOptions {
+        FocusOptions {
+            minimize_on_repeat: self.minimize_on_repeat,
+            policy: self.policy,
+            output: self.output.clone(),
+            summon: self.summon,
+            ..self.launch.options()
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+struct CloseArgs {
+    #[command(flatten)]
+    target: TargetArgs,
+    /// Close only the app's focused window
+    #[arg(long)]
+    focused_only: bool,
+    /// Milliseconds to give the windows before sending SIGTERM to the app's systemd scope
+    #[arg(long, value_name = "MS", conflicts_with = "focused_only")]
+    kill_after: Option<u64>,
+}
+
+impl CloseArgs {
+    fn options(&self) -> FocusOptions {
+        FocusOptions {
+            focused_only: self.focused_only,
+            kill_after: self.kill_after.map(Duration::from_millis),
+            ..self.target.options()
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+struct ListArgs {
+    /// Print running app ids instead of windows
+    #[arg(long)]
+    apps: bool,
+    /// Print a JSON array instead of one line per entry
+    #[arg(long)]
+    json: bool,
+    /// Order of the windows
+    #[arg(long, value_enum, default_value_t = ToplevelOrder::Opened)]
+    sort: ToplevelOrder,
+    /// Ask the compositor directly even if a focus daemon is listening
+    #[arg(long)]
+    no_daemon: bool,
+    #[command(flatten)]
+    discovery: DiscoveryArgs,
+}
+
+#[derive(Args, Debug)]
+struct WatchArgs {
+    /// Print every window change as a JSON event (newline-delimited)
+    #[arg(long)]
+    json: bool,
+    #[command(flatten)]
+    discovery: DiscoveryArgs,
+}
+
+/// Exit status for a failed run:
+///
+/// | Code | Meaning |
+/// | --- | --- |
+/// | 1 | Wayland communication failed |
+/// | 2 | invalid arguments or match pattern |
+/// | 3 | no Wayland compositor reachable |
+/// | 4 | compositor lacks a required protocol |
+/// | 5 | the app could not be launched |
+/// | 6 | the app was launched but its window never appeared (`--wait`) |
+/// | 7 | the focus daemon failed or could not be reached (`previous` needs it running) |
+/// | 8 | no previously focused app to switch back to (`previous`) |
+fn exit_code(err: &FocusError) -> u8 {
+    match err.kind() {
+        FocusErrorKind::Wayland => 1,
+        FocusErrorKind::InvalidPattern => 2,
+        FocusErrorKind::NoCompositor => 3,
+        FocusErrorKind::MissingProtocol => 4,
+        FocusErrorKind::LaunchFailed => 5,
+        FocusErrorKind::NeverAppeared => 6,
+        FocusErrorKind::Daemon => 7,
+        FocusErrorKind::NoHistory => 8,
+    }
+}
+
+/// Moves verbosity flags that lead the command line behind the argument that follows them.
+///
+/// `-v` is global, but clap takes any flag before a subcommand as the start of the focus
+/// shorthand, so `-v list` would focus an app called `list`. Behind the subcommand or app id
+/// they count the same.
+fn hoist_verbosity(args: impl IntoIterator<Item = OsString>) -> Vec<OsString> {
+    let mut args: Vec<OsString> = args.into_iter().collect();
+    let leading = args
+        .iter()
+        .skip(1)
+        .take_while(|arg| is_verbosity_flag(arg))
+        .count();
+    let followed_by_word = args
+        .get(leading + 1)
+        .is_some_and(|next| !next.to_string_lossy().starts_with('-'));
+    if leading > 0 && followed_by_word {
+        args[1..leading + 2].rotate_left(leading);
+    }
+    args
+}
+
+fn is_verbosity_flag(arg: &OsStr) -> bool {
+    arg == "--verbose"
+        || arg
+            .to_str()
+            .and_then(|arg| arg.strip_prefix('-'))
+            .is_some_and(|flags| !flags.is_empty() && flags.chars().all(|c| c == 'v'))
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse_from(hoist_verbosity(env::args_os()));
+    focus::init_logger(cli.verbose);
+    let result = match cli.command {
+        None => focus_app(&cli.focus),
+        Some(Command::Focus(args)) => focus_app(&args),
+        Some(Command::Launch(args)) => focus::launch_app(args.target.app_id(), &args.options()),
+        Some(Command::Previous) => focus::focus_previous(),
+        Some(Command::List(args)) => list(&args),
+        Some(Command::Watch(args)) => watch(&args),
+        Some(Command::Close(args)) => {
+            focus::close_app(args.target.app_id(), &args.options()).map(drop)
+        }
+        Some(Command::Minimize(args)) => {
+            focus::minimize_app(args.app_id(), &args.options()).map(drop)
+        }
+        Some(Command::Info(args)) => info(&args),
+        Some(Command::Daemon(args)) => focus::run_daemon(args.timeout_or_default()),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {err}");
+            ExitCode::from(exit_code(&err))
+        }
+    }
+}
+
+fn focus_app(args: &FocusArgs) -> Result<(), FocusError> {
+    let app_id = args.launch.target.app_id();
+    let options = args.options();
+    let forwarded = if args.no_daemon {
+        None
+    } else {
+        focus::forward_to_daemon(app_id, &options)
+    };
+    let outcome = forwarded.unwrap_or_else(|| focus::focus_or_launch(app_id, &options))?;
+    log::info!("{}: {:?}", app_id, outcome);
+    Ok(())
+}
+
+fn list(args: &ListArgs) -> Result<(), FocusError> {
+    if args.apps {
+        let apps = focus::list_running_apps(args.discovery.timeout_or_default())?;
+        if args.json {
+            print_json(&apps);
+        } else {
+            for app_id in apps {
+                println!("{app_id}");
+            }
+        }
+    } else {
+        let forwarded = if args.no_daemon {
+            None
+        } else {
+            focus::forward_list(args.sort)
+        };
+        let toplevels = forwarded.unwrap_or_else(|| {
+            focus::list_toplevels(args.sort, args.discovery.timeout_or_default())
+        })?;
+        if args.json {
+            print_json(&toplevels);
+        } else {
+            for toplevel in toplevels {
+                println!("{}", format_toplevel(&toplevel));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn watch(args: &WatchArgs) -> Result<(), FocusError> {
+    let (tx, rx) = mpsc::channel();
+    let watcher = focus::watch_toplevels(tx, args.discovery.timeout_or_default())?;
+    let mut running = RunningApps::default();
+    for event in rx {
+        if args.json {
+            print_json(&event);
+        } else if running.apply(&event) {
+            println!("{}", running.apps().join(" "));
+        }
+    }
+    watcher.stop()
+}
+
+/// Prints `value` as a single line of JSON.
+fn print_json(value: &impl Serialize) {
+    println!(
+        "{}",
+        serde_json::to_string(value).expect("output types always serialize")
+    );
+}
+
+fn info(args: &TargetArgs) -> Result<(), FocusError> {
+    let app_id = args.app_id();
+    match focus::find_desktop_entry(app_id) {
+        Some(entry) => {
+            println!("Desktop entry: {}", entry.path.display());
+            for key in ["Name", "Exec", "Icon", "StartupWMClass", "DBusActivatable"] {
+                if let Some(value) = entry.desktop_entry(key) {
+                    println!("{key}: {value}");
+                }
+            }
+        }
+        None => println!("Desktop entry: none found for '{app_id}'"),
+    }
+
+    let windows = focus::app_toplevels(app_id, &args.options())?;
+    println!("Windows: {}", windows.len());
+    for toplevel in &windows {
+        println!("  {}", format_toplevel(toplevel));
+    }
+    Ok(())
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
-    focus::init_logger(args.verbose);
-    focus::focus_or_launch(&args.app_id, args.launch_cmd.as_deref())
+/// One tab-separated line: id, app_id, states, outputs, workspaces and title.
+fn format_toplevel(toplevel: &Toplevel) -> String {
+    let states: Vec<&str> = [
+        (toplevel.activated, "activated"),
+        (toplevel.minimized, "minimized"),
+        (toplevel.maximized, "maximized"),
+        (toplevel.fullscreen, "fullscreen"),
+    ]
+    .into_iter()
+    .filter_map(|(set, name)| set.then_some(name))
+    .collect();
+    format!(
+        "{}\t{}\t{}\t{}\t{}\t{}",
+        toplevel.id,
+        toplevel.app_id,
+        if states.is_empty() {
+            "-".to_string()
+        } else {
+            states.join(",")
+        },
+        toplevel.outputs.join(","),
+        toplevel.workspaces.join(","),
+        toplevel.title
+    )
 }